@@ -0,0 +1,419 @@
+use anyhow::{Result, Context as _, anyhow};
+use serde::{Serialize, Deserialize};
+
+use crate::matrix::{Player, PlayerId};
+use crate::results::{MatchFormat, MatchResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Section{
+    Winners, Losers, GrandFinal, GrandFinalReset
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Slot{
+    //Waiting on an earlier match
+    Pending,
+    Player(PlayerId),
+    //Nobody will arrive, so the other player goes through
+    Bye,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BracketMatch{
+    pub section: Section,
+    pub round: usize,
+    pub slots: [Slot; 2],
+    //From the point of view of the first slot
+    pub result: Option<MatchResult>,
+    pub reporter: Option<PlayerId>,
+    //Match index and slot the winner and loser move on to
+    pub winner_to: Option<(usize, usize)>,
+    pub loser_to: Option<(usize, usize)>,
+}
+impl BracketMatch{
+    fn new(section: Section, round: usize) -> Self{
+        BracketMatch{section, round, slots: [Slot::Pending; 2], result: None, reporter: None, winner_to: None, loser_to: None}
+    }
+    //The winning and losing slots once the match is decided
+    pub fn outcome(&self) -> Option<(Slot, Slot)>{
+        match (self.slots, &self.result) {
+            ([Slot::Bye, other], _) | ([other, Slot::Bye], _) if other != Slot::Pending => Some((other, Slot::Bye)),
+            ([first, second], Some(result)) if result.is_win() => Some((first, second)),
+            ([first, second], Some(_)) => Some((second, first)),
+            _ => None,
+        }
+    }
+    pub fn has_player(&self, player: PlayerId) -> bool{
+        self.slots.contains(&Slot::Player(player))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bracket{
+    //Players in seed order
+    pub users: Vec<Player>,
+    pub format: MatchFormat,
+    pub double: bool,
+    pub matches: Vec<BracketMatch>,
+    //Indexes of the decided matches in the order they were reported
+    #[serde(default)]
+    pub reports: Vec<usize>,
+}
+impl Bracket{
+    //Builds a bracket for the seeded players, padded with byes for the top seeds up to a power of two
+    pub fn new(users: Vec<Player>, format: MatchFormat, double: bool) -> Result<Self>{
+        if users.len() < 2 {
+            return Err(anyhow!("a bracket needs at least 2 players"));
+        }
+        let size = users.len().next_power_of_two();
+        let winners_rounds = size.ilog2() as usize;
+        let mut matches = Vec::new();
+        //Winners bracket, round by round
+        let mut round_start = Vec::new();
+        for round in 0..winners_rounds{
+            round_start.push(matches.len());
+            for i in 0..size >> (round+1){
+                let mut bracket_match = BracketMatch::new(Section::Winners, round);
+                if round+1 < winners_rounds {
+                    bracket_match.winner_to = Some((matches.len() - i + (size >> (round+1)) + i/2, i%2));
+                }
+                matches.push(bracket_match);
+            }
+        }
+        for (i, seeds) in seed_order(size).chunks(2).enumerate(){
+            for (slot, seed) in seeds.iter().enumerate(){
+                matches[i].slots[slot] = users.get(*seed).map_or(Slot::Bye, |x| Slot::Player(x.id));
+            }
+        }
+        let winners_final = matches.len()-1;
+
+        if double {
+            //Losers bracket alternates between a round within the losers bracket and a round taking in the next winners round's losers
+            let mut previous_round: Vec<usize> = Vec::new();
+            for round in 0..2*(winners_rounds-1){
+                let count = size >> (round/2 + 2);
+                let start = matches.len();
+                for i in 0..count{
+                    matches.push(BracketMatch::new(Section::Losers, round));
+                    if round == 0 {
+                        matches[round_start[0] + 2*i].loser_to = Some((start+i, 0));
+                        matches[round_start[0] + 2*i+1].loser_to = Some((start+i, 1));
+                    } else if round % 2 == 1 {
+                        matches[previous_round[i]].winner_to = Some((start+i, 0));
+                        //Drop in reversed so players don't meet again straight away
+                        matches[round_start[round/2+1] + count-1-i].loser_to = Some((start+i, 1));
+                    } else {
+                        matches[previous_round[2*i]].winner_to = Some((start+i, 0));
+                        matches[previous_round[2*i+1]].winner_to = Some((start+i, 1));
+                    }
+                }
+                previous_round = (start..start+count).collect();
+            }
+            let grand_final = matches.len();
+            matches.push(BracketMatch::new(Section::GrandFinal, 0));
+            matches.push(BracketMatch::new(Section::GrandFinalReset, 0));
+            matches[winners_final].winner_to = Some((grand_final, 0));
+            match previous_round.last() {
+                Some(losers_final) => matches[*losers_final].winner_to = Some((grand_final, 1)),
+                None => matches[winners_final].loser_to = Some((grand_final, 1)),
+            }
+        }
+        let mut bracket = Bracket{users, format, double, matches, reports: Vec::new()};
+        bracket.advance_byes();
+        Ok(bracket)
+    }
+
+    //Moves players on from decided matches, including matches decided by a bye
+    fn advance_byes(&mut self){
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in 0..self.matches.len(){
+                let Some((winner, loser)) = self.matches[i].outcome() else { continue };
+                for (target, slot) in [(self.matches[i].winner_to, winner), (self.matches[i].loser_to, loser)]{
+                    if let Some((target, target_slot)) = target {
+                        if self.matches[target].slots[target_slot] != slot {
+                            self.matches[target].slots[target_slot] = slot;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+            //The grand final is only replayed if the losers bracket winner takes the first one
+            if let Some(grand_final) = self.matches.iter().position(|x| x.section == Section::GrandFinal) {
+                let reset = grand_final+1;
+                let slots = match self.matches[grand_final].outcome() {
+                    Some((winner, _)) if winner == self.matches[grand_final].slots[1] => self.matches[grand_final].slots,
+                    Some(_) => [Slot::Bye, Slot::Bye],
+                    None => [Slot::Pending, Slot::Pending],
+                };
+                if self.matches[reset].slots != slots {
+                    self.matches[reset].slots = slots;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    //The unfinished match between the two players, if they're due to play
+    pub fn find_match(&self, player: PlayerId, opponent: PlayerId) -> Option<usize>{
+        self.matches.iter().position(|x| x.has_player(player) && x.has_player(opponent) && x.result.is_none())
+    }
+    pub fn current_opponent(&self, player: PlayerId) -> Option<PlayerId>{
+        self.matches.iter().filter(|x| x.has_player(player) && x.result.is_none()).find_map(|x| x.slots.iter().find_map(|slot| match slot {
+            Slot::Player(id) if *id != player => Some(*id),
+            _ => None,
+        }))
+    }
+
+    //Records the result, from the point of view of the given player, and moves the winner and loser on
+    pub fn report(&mut self, player: PlayerId, opponent: PlayerId, result: MatchResult, reporter: PlayerId) -> Result<()>{
+        self.check_score(&result)?;
+        let index = self.find_match(player, opponent).context("those players don't have a match to play right now")?;
+        self.set_result(index, player, result, reporter);
+        Ok(())
+    }
+    //Replaces a decided match's result while nothing it fed into has been played
+    pub fn rereport(&mut self, player: PlayerId, opponent: PlayerId, result: MatchResult, reporter: PlayerId) -> Result<()>{
+        self.check_score(&result)?;
+        let index = self.matches.iter().rposition(|x| x.has_player(player) && x.has_player(opponent) && x.result.is_some())
+            .context("those players don't have a match to play right now")?;
+        self.clear_after(index)?;
+        self.set_result(index, player, result, reporter);
+        Ok(())
+    }
+    //Takes the result off a decided match while nothing it fed into has been played
+    pub fn undo(&mut self, index: usize) -> Result<()>{
+        if self.matches.get(index).is_none_or(|x| x.result.is_none()) {
+            return Err(anyhow!("that match hasn't been reported"));
+        }
+        self.clear_after(index)?;
+        self.matches[index].result = None;
+        self.matches[index].reporter = None;
+        self.reports.retain(|x| *x != index);
+        self.advance_byes();
+        Ok(())
+    }
+    fn check_score(&self, result: &MatchResult) -> Result<()>{
+        if !result.is_played() || *result == MatchResult::Draw || !self.format.allows(result) {
+            return Err(anyhow!("{} is not a valid score for a {} bracket match", result.to_str(), self.format.name()));
+        }
+        Ok(())
+    }
+    fn set_result(&mut self, index: usize, player: PlayerId, result: MatchResult, reporter: PlayerId){
+        let bracket_match = &mut self.matches[index];
+        bracket_match.result = Some(if bracket_match.slots[0] == Slot::Player(player) {result} else {result.invert()});
+        bracket_match.reporter = Some(reporter);
+        self.reports.retain(|x| *x != index);
+        self.reports.push(index);
+        self.advance_byes();
+    }
+    //Empties the slots the match filled, following players passed straight on by a bye
+    fn clear_after(&mut self, index: usize) -> Result<()>{
+        let mut fed = Vec::new();
+        let mut queue = vec![index];
+        while let Some(i) = queue.pop(){
+            let bracket_match = &self.matches[i];
+            let reset = (bracket_match.section == Section::GrandFinal).then_some([(i+1, 0), (i+1, 1)]);
+            for (target, slot) in [bracket_match.winner_to, bracket_match.loser_to].into_iter().flatten().chain(reset.into_iter().flatten()){
+                if self.matches[target].result.is_some() {
+                    return Err(anyhow!("a later match has already been played, undo that first"));
+                }
+                fed.push((target, slot));
+                queue.push(target);
+            }
+        }
+        for (target, slot) in fed{
+            self.matches[target].slots[slot] = Slot::Pending;
+        }
+        Ok(())
+    }
+
+    pub fn champion(&self) -> Option<PlayerId>{
+        let last = self.matches.iter().rev().find(|x| x.slots != [Slot::Bye, Slot::Bye])?;
+        match last.outcome()?.0 {
+            Slot::Player(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    fn slot_name(&self, slot: Slot) -> String{
+        match slot {
+            Slot::Pending => "TBD".to_string(),
+            Slot::Bye => "bye".to_string(),
+            Slot::Player(id) => match self.users.iter().position(|x| x.id == id) {
+                Some(seed) => format!("({}) {}", seed+1, self.users[seed].name),
+                None => format!("<@{}>", id),
+            },
+        }
+    }
+
+    //The players with the winner in bold, or "vs" while undecided
+    pub fn match_line(&self, bracket_match: &BracketMatch) -> String{
+        let [first, second] = bracket_match.slots.map(|x| self.slot_name(x));
+        match (&bracket_match.result, bracket_match.outcome()) {
+            (Some(result), Some((winner, _))) if winner == bracket_match.slots[0] => format!("**{}** {} {}", first, result.to_str(), second),
+            (Some(result), Some(_)) => format!("{} {} **{}**", first, result.to_str(), second),
+            _ => format!("{} vs {}", first, second),
+        }
+    }
+
+    pub fn render_lines(&self, title: &str) -> Vec<String>{
+        let mut lines = vec![title.to_string()];
+        let mut heading = None;
+        for bracket_match in &self.matches{
+            //Matches with a bye aren't really played, so leave them out
+            if bracket_match.slots.contains(&Slot::Bye) { continue }
+            let match_heading = match bracket_match.section {
+                Section::Winners if !self.double => format!("**Round {}**", bracket_match.round+1),
+                Section::Winners => format!("**Winners round {}**", bracket_match.round+1),
+                Section::Losers => format!("**Losers round {}**", bracket_match.round+1),
+                Section::GrandFinal => "**Grand final**".to_string(),
+                Section::GrandFinalReset => "**Grand final reset**".to_string(),
+            };
+            if heading.as_ref() != Some(&match_heading) {
+                lines.push(match_heading.clone());
+                heading = Some(match_heading);
+            }
+            lines.push(self.match_line(bracket_match));
+        }
+        if let Some(champion) = self.champion() {
+            lines.push(format!(":trophy: {}", self.slot_name(Slot::Player(champion))));
+        }
+        lines
+    }
+
+    pub fn render(&self, title: &str, message_count: usize) -> Vec<String>{
+        let lines = self.render_lines(title);
+        let lines_per_message = lines.len().div_ceil(message_count).max(1);
+        let mut messages: Vec<String> = lines.chunks(lines_per_message).map(|x| x.join("\n")).collect();
+        messages.resize(message_count, "_ _".to_string());
+        messages
+    }
+    //Enough messages for the bracket once every name is filled in
+    pub fn message_count(&self) -> usize{
+        let approx_char_count = (self.matches.len()*2 + 2)*60;
+        (approx_char_count/1800)+1
+    }
+}
+
+//Zero based seeds in bracket order, so the top seeds meet as late as possible
+fn seed_order(size: usize) -> Vec<usize>{
+    let mut order = vec![0];
+    while order.len() < size {
+        let round_size = order.len()*2;
+        order = order.iter().flat_map(|x| [*x, round_size-1-x]).collect();
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::matrix::tests::players;
+
+    //Plays the bracket out with the better seed always winning, returning how many matches were played
+    fn play_out(bracket: &mut Bracket) -> usize{
+        let mut played = 0;
+        while bracket.champion().is_none() {
+            let (player, opponent) = bracket.matches.iter().filter(|x| x.result.is_none()).find_map(|x| match x.slots {
+                [Slot::Player(a), Slot::Player(b)] => Some((a.min(b), a.max(b))),
+                _ => None,
+            }).expect("bracket stalled with no playable match");
+            bracket.report(player, opponent, MatchResult::Score(2, 1), player).unwrap();
+            played += 1;
+        }
+        played
+    }
+
+    #[test]
+    fn top_seeds_get_the_byes() {
+        let bracket = Bracket::new(players(6), MatchFormat::default(), false).unwrap();
+        //Seeds 1 and 2 go straight through to the semi finals
+        assert_eq!(bracket.current_opponent(PlayerId(1)), None);
+        assert_eq!(bracket.current_opponent(PlayerId(3)), Some(PlayerId(6)));
+        assert_eq!(bracket.current_opponent(PlayerId(4)), Some(PlayerId(5)));
+        assert!(Bracket::new(players(1), MatchFormat::default(), false).is_err());
+    }
+
+    #[test]
+    fn single_elimination_takes_a_match_per_eliminated_player() {
+        for count in 2..=9{
+            let mut bracket = Bracket::new(players(count), MatchFormat::default(), false).unwrap();
+            assert_eq!(play_out(&mut bracket), count as usize - 1);
+            assert_eq!(bracket.champion(), Some(PlayerId(1)));
+        }
+    }
+
+    #[test]
+    fn double_elimination_ends_without_a_reset_when_the_winners_side_wins() {
+        for count in 2..=9{
+            let mut bracket = Bracket::new(players(count), MatchFormat::default(), true).unwrap();
+            assert_eq!(play_out(&mut bracket), 2*count as usize - 2);
+            assert_eq!(bracket.champion(), Some(PlayerId(1)));
+        }
+    }
+
+    #[test]
+    fn grand_final_resets_when_the_losers_side_wins() {
+        let mut bracket = Bracket::new(players(2), MatchFormat::default(), true).unwrap();
+        bracket.report(PlayerId(1), PlayerId(2), MatchResult::Score(2, 0), PlayerId(1)).unwrap();
+        //Player 2 comes back through the losers side and takes the first grand final
+        bracket.report(PlayerId(2), PlayerId(1), MatchResult::Score(2, 0), PlayerId(2)).unwrap();
+        assert_eq!(bracket.champion(), None);
+        assert_eq!(bracket.current_opponent(PlayerId(1)), Some(PlayerId(2)));
+        bracket.report(PlayerId(1), PlayerId(2), MatchResult::Score(2, 1), PlayerId(1)).unwrap();
+        assert_eq!(bracket.champion(), Some(PlayerId(1)));
+    }
+
+    #[test]
+    fn reports_need_a_decisive_score_for_a_due_match() {
+        let mut bracket = Bracket::new(players(4), MatchFormat::default(), false).unwrap();
+        assert!(bracket.report(PlayerId(1), PlayerId(4), MatchResult::Draw, PlayerId(1)).is_err());
+        assert!(bracket.report(PlayerId(1), PlayerId(4), MatchResult::Score(2, 2), PlayerId(1)).is_err());
+        assert!(bracket.report(PlayerId(1), PlayerId(2), MatchResult::Score(2, 0), PlayerId(1)).is_err());
+        bracket.report(PlayerId(4), PlayerId(1), MatchResult::Score(0, 2), PlayerId(4)).unwrap();
+        assert_eq!(bracket.matches[0].reporter, Some(PlayerId(4)));
+        assert!(bracket.render_lines("Playoffs").iter().any(|x| x.contains("(1) player1")));
+    }
+
+    #[test]
+    fn decided_matches_change_until_a_later_match_is_played() {
+        let mut bracket = Bracket::new(players(4), MatchFormat::default(), true).unwrap();
+        bracket.report(PlayerId(1), PlayerId(4), MatchResult::Score(2, 0), PlayerId(1)).unwrap();
+        assert!(bracket.rereport(PlayerId(2), PlayerId(3), MatchResult::Score(2, 0), PlayerId(2)).is_err());
+        //Player 4 goes through instead, and player 1 drops to the losers side
+        bracket.rereport(PlayerId(1), PlayerId(4), MatchResult::Score(1, 2), PlayerId(9)).unwrap();
+        assert_eq!(bracket.matches[0].reporter, Some(PlayerId(9)));
+        bracket.report(PlayerId(3), PlayerId(2), MatchResult::Score(0, 2), PlayerId(3)).unwrap();
+        assert_eq!(bracket.current_opponent(PlayerId(4)), Some(PlayerId(2)));
+        assert_eq!(bracket.current_opponent(PlayerId(1)), Some(PlayerId(3)));
+        assert_eq!(bracket.reports, vec![0, 1]);
+
+        bracket.report(PlayerId(1), PlayerId(3), MatchResult::Score(2, 0), PlayerId(1)).unwrap();
+        assert!(bracket.undo(0).is_err());
+        bracket.undo(bracket.reports[2]).unwrap();
+        bracket.undo(0).unwrap();
+        assert_eq!(bracket.current_opponent(PlayerId(1)), Some(PlayerId(4)));
+        assert_eq!(bracket.current_opponent(PlayerId(3)), None);
+        assert!(bracket.undo(0).is_err());
+        assert_eq!(bracket.reports, vec![1]);
+        assert_eq!(play_out(&mut bracket), 5);
+        assert_eq!(bracket.champion(), Some(PlayerId(1)));
+    }
+
+    #[test]
+    fn corrections_follow_players_passed_on_by_a_bye() {
+        let mut bracket = Bracket::new(players(3), MatchFormat::default(), true).unwrap();
+        bracket.report(PlayerId(2), PlayerId(3), MatchResult::Score(2, 0), PlayerId(2)).unwrap();
+        assert_eq!(bracket.current_opponent(PlayerId(3)), None);
+        bracket.report(PlayerId(1), PlayerId(2), MatchResult::Score(2, 0), PlayerId(1)).unwrap();
+        //Player 3 skipped the first losers round on a bye, so the correction reaches the losers final
+        assert_eq!(bracket.current_opponent(PlayerId(3)), Some(PlayerId(2)));
+        assert!(bracket.rereport(PlayerId(2), PlayerId(3), MatchResult::Score(0, 2), PlayerId(2)).is_err());
+        bracket.undo(bracket.reports[1]).unwrap();
+        bracket.rereport(PlayerId(2), PlayerId(3), MatchResult::Score(0, 2), PlayerId(2)).unwrap();
+        assert_eq!(bracket.current_opponent(PlayerId(1)), Some(PlayerId(3)));
+        assert_eq!(bracket.current_opponent(PlayerId(2)), None);
+    }
+}
@@ -0,0 +1,282 @@
+use serenity::all::*;
+use anyhow::Result;
+
+#[derive(Debug, Clone)]
+pub struct PostedMessage{
+    pub id: MessageId,
+    pub author_bot: bool,
+    pub content: String,
+}
+
+//The Discord operations the bot makes outside of replying to interactions
+#[async_trait]
+pub trait Discord: Send + Sync{
+    async fn create_thread(&self, channel: ChannelId, name: &str) -> Result<ChannelId>;
+    async fn send_message(&self, channel: ChannelId, message: CreateMessage) -> Result<MessageId>;
+    async fn edit_message(&self, channel: ChannelId, message: MessageId, edit: EditMessage) -> Result<()>;
+    async fn delete_message(&self, channel: ChannelId, message: MessageId) -> Result<()>;
+    //Posts a message that is only the attached files, given as names and contents
+    async fn send_files(&self, channel: ChannelId, files: Vec<(String, Vec<u8>)>) -> Result<MessageId>;
+    async fn replace_file(&self, channel: ChannelId, message: MessageId, filename: &str, data: Vec<u8>) -> Result<()>;
+    async fn download(&self, attachment: &Attachment) -> Result<Vec<u8>>;
+    //The first messages in the channel, newest first
+    async fn messages(&self, channel: ChannelId) -> Result<Vec<PostedMessage>>;
+    async fn set_guild_commands(&self, guild: GuildId, commands: Vec<CreateCommand>) -> Result<()>;
+    async fn member_name(&self, guild: GuildId, user: UserId) -> Result<String>;
+    //Every member of the guild with the role, needs the server members intent
+    async fn role_members(&self, guild: GuildId, role: RoleId) -> Result<Vec<Member>>;
+    //Everyone who reacted to the message, with any emoji, each listed once
+    async fn reaction_users(&self, channel: ChannelId, message: MessageId) -> Result<Vec<User>>;
+
+    async fn say(&self, channel: ChannelId, content: String) -> Result<MessageId>{
+        self.send_message(channel, CreateMessage::new().content(content)).await
+    }
+    async fn send_file(&self, channel: ChannelId, filename: &str, data: Vec<u8>) -> Result<MessageId>{
+        self.send_files(channel, vec![(filename.to_string(), data)]).await
+    }
+}
+
+#[async_trait]
+impl Discord for Http{
+    async fn create_thread(&self, channel: ChannelId, name: &str) -> Result<ChannelId>{
+        Ok(channel.create_thread(self, CreateThread::new(name).kind(ChannelType::PublicThread)).await?.id)
+    }
+    async fn send_message(&self, channel: ChannelId, message: CreateMessage) -> Result<MessageId>{
+        Ok(channel.send_message(self, message).await?.id)
+    }
+    async fn edit_message(&self, channel: ChannelId, message: MessageId, edit: EditMessage) -> Result<()>{
+        channel.edit_message(self, message, edit).await?;
+        Ok(())
+    }
+    async fn delete_message(&self, channel: ChannelId, message: MessageId) -> Result<()>{
+        channel.delete_message(self, message).await?;
+        Ok(())
+    }
+    async fn send_files(&self, channel: ChannelId, files: Vec<(String, Vec<u8>)>) -> Result<MessageId>{
+        let files = files.into_iter().map(|(filename, data)| CreateAttachment::bytes(data, filename));
+        Ok(channel.send_message(self, CreateMessage::new().add_files(files)).await?.id)
+    }
+    async fn replace_file(&self, channel: ChannelId, message: MessageId, filename: &str, data: Vec<u8>) -> Result<()>{
+        channel.edit_message(self, message, EditMessage::new().attachments(EditAttachments::new().add(CreateAttachment::bytes(data, filename)))).await?;
+        Ok(())
+    }
+    async fn download(&self, attachment: &Attachment) -> Result<Vec<u8>>{
+        Ok(attachment.download().await?)
+    }
+    async fn messages(&self, channel: ChannelId) -> Result<Vec<PostedMessage>>{
+        let messages = channel.messages(self, GetMessages::new().after(1)).await?;
+        Ok(messages.into_iter().map(|x| PostedMessage{id: x.id, author_bot: x.author.bot, content: x.content}).collect())
+    }
+    async fn set_guild_commands(&self, guild: GuildId, commands: Vec<CreateCommand>) -> Result<()>{
+        guild.set_commands(self, commands).await?;
+        Ok(())
+    }
+    async fn member_name(&self, guild: GuildId, user: UserId) -> Result<String>{
+        Ok(guild.member(self, user).await?.display_name().to_string())
+    }
+    async fn role_members(&self, guild: GuildId, role: RoleId) -> Result<Vec<Member>>{
+        //Discord lists members a page of at most 1000 at a time
+        let mut users = Vec::new();
+        let mut after = None;
+        loop{
+            let members = guild.members(self, Some(1000), after).await?;
+            after = members.last().map(|x| x.user.id);
+            let full_page = members.len() == 1000;
+            users.extend(members.into_iter().filter(|x| x.roles.contains(&role)));
+            if !full_page { return Ok(users) }
+        }
+    }
+    async fn reaction_users(&self, channel: ChannelId, message: MessageId) -> Result<Vec<User>>{
+        let reactions = channel.message(self, message).await?.reactions;
+        let mut users: Vec<User> = Vec::new();
+        for reaction in reactions{
+            let mut after = None;
+            loop{
+                let page = channel.reaction_users(self, message, reaction.reaction_type.clone(), Some(100), after).await?;
+                after = page.last().map(|x| x.id);
+                let full_page = page.len() == 100;
+                for user in page{
+                    if !users.iter().any(|x| x.id == user.id) {
+                        users.push(user);
+                    }
+                }
+                if !full_page { break }
+            }
+        }
+        Ok(users)
+    }
+}
+
+//In-memory stand-in for Discord that records everything the bot posts
+#[cfg(test)]
+pub mod fake{
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use serde_json::Value;
+
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    pub struct FakeMessage{
+        pub channel: ChannelId,
+        pub id: MessageId,
+        pub content: String,
+        pub buttons: Vec<String>,
+        pub files: Vec<(String, Vec<u8>)>,
+        pub edits: usize,
+    }
+
+    #[derive(Default)]
+    struct State{
+        next_id: u64,
+        threads: Vec<(ChannelId, String)>,
+        messages: Vec<FakeMessage>,
+        commands: HashMap<GuildId, Vec<Value>>,
+        members: HashMap<UserId, String>,
+        roles: HashMap<RoleId, Vec<UserId>>,
+        reactions: HashMap<MessageId, Vec<UserId>>,
+        uploads: HashMap<String, Vec<u8>>,
+    }
+
+    #[derive(Default)]
+    pub struct FakeDiscord{
+        state: Mutex<State>,
+    }
+    impl FakeDiscord{
+        pub fn new() -> Self{
+            //Keep fake ids well clear of the user and channel ids tests pick
+            FakeDiscord{state: Mutex::new(State{next_id: 1_000_000, ..Default::default()})}
+        }
+        pub fn add_member(&self, user: UserId, name: &str){
+            self.state.lock().unwrap().members.insert(user, name.to_string());
+        }
+        pub fn give_role(&self, user: UserId, role: RoleId){
+            self.state.lock().unwrap().roles.entry(role).or_default().push(user);
+        }
+        pub fn react(&self, message: MessageId, user: UserId){
+            self.state.lock().unwrap().reactions.entry(message).or_default().push(user);
+        }
+        fn user(id: UserId) -> User{
+            let mut user = User::default();
+            user.id = id;
+            user.name = format!("user{}", id);
+            user
+        }
+        pub fn upload(&self, url: &str, data: &[u8]){
+            self.state.lock().unwrap().uploads.insert(url.to_string(), data.to_vec());
+        }
+        pub fn threads(&self) -> Vec<(ChannelId, String)>{
+            self.state.lock().unwrap().threads.clone()
+        }
+        //Every message posted in the channel, oldest first
+        pub fn messages_in(&self, channel: ChannelId) -> Vec<FakeMessage>{
+            self.state.lock().unwrap().messages.iter().filter(|x| x.channel == channel).cloned().collect()
+        }
+        pub fn command_names(&self, guild: GuildId) -> Vec<String>{
+            self.state.lock().unwrap().commands.get(&guild).into_iter().flatten()
+                .filter_map(|x| x["name"].as_str().map(|x| x.to_string())).collect()
+        }
+        pub fn command(&self, guild: GuildId, name: &str) -> Option<Value>{
+            self.state.lock().unwrap().commands.get(&guild)?.iter().find(|x| x["name"] == name).cloned()
+        }
+        fn next_id(state: &mut State) -> u64{
+            state.next_id += 1;
+            state.next_id
+        }
+    }
+
+    fn buttons(message: &Value) -> Option<Vec<String>>{
+        let rows = message.get("components")?.as_array()?;
+        Some(rows.iter().flat_map(|row| row["components"].as_array().into_iter().flatten())
+            .filter_map(|x| x["custom_id"].as_str().map(|x| x.to_string())).collect())
+    }
+
+    #[async_trait]
+    impl Discord for FakeDiscord{
+        async fn create_thread(&self, _channel: ChannelId, name: &str) -> Result<ChannelId>{
+            let mut state = self.state.lock().unwrap();
+            let thread = ChannelId::new(Self::next_id(&mut state));
+            state.threads.push((thread, name.to_string()));
+            Ok(thread)
+        }
+        async fn send_message(&self, channel: ChannelId, message: CreateMessage) -> Result<MessageId>{
+            let message = serde_json::to_value(message)?;
+            let mut state = self.state.lock().unwrap();
+            let id = MessageId::new(Self::next_id(&mut state));
+            state.messages.push(FakeMessage{channel, id, content: message["content"].as_str().unwrap_or_default().to_string(),
+                buttons: buttons(&message).unwrap_or_default(), files: Vec::new(), edits: 0});
+            Ok(id)
+        }
+        async fn edit_message(&self, channel: ChannelId, message: MessageId, edit: EditMessage) -> Result<()>{
+            let edit = serde_json::to_value(edit)?;
+            let mut state = self.state.lock().unwrap();
+            let posted = state.messages.iter_mut().find(|x| x.channel == channel && x.id == message)
+                .ok_or_else(|| anyhow::anyhow!("no message {} in {}", message, channel))?;
+            if let Some(content) = edit["content"].as_str() {
+                posted.content = content.to_string();
+            }
+            if let Some(buttons) = buttons(&edit) {
+                posted.buttons = buttons;
+            }
+            posted.edits += 1;
+            Ok(())
+        }
+        async fn delete_message(&self, channel: ChannelId, message: MessageId) -> Result<()>{
+            let mut state = self.state.lock().unwrap();
+            let index = state.messages.iter().position(|x| x.channel == channel && x.id == message)
+                .ok_or_else(|| anyhow::anyhow!("no message {} in {}", message, channel))?;
+            state.messages.remove(index);
+            Ok(())
+        }
+        async fn send_files(&self, channel: ChannelId, files: Vec<(String, Vec<u8>)>) -> Result<MessageId>{
+            let mut state = self.state.lock().unwrap();
+            let id = MessageId::new(Self::next_id(&mut state));
+            state.messages.push(FakeMessage{channel, id, content: String::new(), buttons: Vec::new(), files, edits: 0});
+            Ok(id)
+        }
+        async fn replace_file(&self, channel: ChannelId, message: MessageId, filename: &str, data: Vec<u8>) -> Result<()>{
+            let mut state = self.state.lock().unwrap();
+            let posted = state.messages.iter_mut().find(|x| x.channel == channel && x.id == message)
+                .ok_or_else(|| anyhow::anyhow!("no message {} in {}", message, channel))?;
+            posted.files = vec![(filename.to_string(), data)];
+            posted.edits += 1;
+            Ok(())
+        }
+        async fn download(&self, attachment: &Attachment) -> Result<Vec<u8>>{
+            self.state.lock().unwrap().uploads.get(&attachment.url).cloned().ok_or_else(|| anyhow::anyhow!("nothing uploaded at {}", attachment.url))
+        }
+        async fn messages(&self, channel: ChannelId) -> Result<Vec<PostedMessage>>{
+            //Everything in the fake is posted by the bot
+            Ok(self.messages_in(channel).into_iter().rev().map(|x| PostedMessage{id: x.id, author_bot: true, content: x.content}).collect())
+        }
+        async fn set_guild_commands(&self, guild: GuildId, commands: Vec<CreateCommand>) -> Result<()>{
+            let commands = commands.into_iter().map(serde_json::to_value).collect::<Result<_, _>>()?;
+            self.state.lock().unwrap().commands.insert(guild, commands);
+            Ok(())
+        }
+        async fn member_name(&self, _guild: GuildId, user: UserId) -> Result<String>{
+            self.state.lock().unwrap().members.get(&user).cloned().ok_or_else(|| anyhow::anyhow!("unknown member {}", user))
+        }
+        async fn role_members(&self, _guild: GuildId, role: RoleId) -> Result<Vec<Member>>{
+            let state = self.state.lock().unwrap();
+            Ok(state.roles.get(&role).into_iter().flatten().map(|x| {
+                let mut member = Member::default();
+                member.user = Self::user(*x);
+                member.nick = state.members.get(x).cloned();
+                member
+            }).collect())
+        }
+        async fn reaction_users(&self, _channel: ChannelId, message: MessageId) -> Result<Vec<User>>{
+            let state = self.state.lock().unwrap();
+            let users = state.reactions.get(&message).ok_or_else(|| anyhow::anyhow!("no message {}", message))?;
+            let mut unique: Vec<UserId> = Vec::new();
+            for user in users{
+                if !unique.contains(user) {
+                    unique.push(*user);
+                }
+            }
+            Ok(unique.into_iter().map(Self::user).collect())
+        }
+    }
+}
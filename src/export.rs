@@ -0,0 +1,264 @@
+use serde_json::{Value, json};
+
+use crate::matrix::{Matrix, PlayerId, same_match};
+use crate::results::MatchResult;
+use crate::standings::Standing;
+
+//A result report from the history, with its (home) player and opponent as reported
+#[derive(Debug, Clone)]
+pub struct Report{
+    pub player: PlayerId,
+    pub opponent: PlayerId,
+    pub reporter: PlayerId,
+    pub time: String,
+}
+
+//A match with a result, from the row (home) player's point of view
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayedMatch{
+    pub player: PlayerId,
+    pub opponent: PlayerId,
+    pub result: MatchResult,
+    //Whoever last set the result and when, unless it was only read back from the thread
+    pub reporter: Option<PlayerId>,
+    pub time: Option<String>,
+}
+
+//Every played match once in player order, with the latest of the reports that set it
+pub fn played_matches(matrix: &Matrix, reports: &[Report]) -> Vec<PlayedMatch> {
+    let mut matches = Vec::new();
+    for (row, player) in matrix.users.iter().enumerate(){
+        for (column, opponent) in matrix.users.iter().enumerate(){
+            //Both sides of a single round-robin match hold the same result
+            if row == column || (!matrix.double && column < row) { continue }
+            let Some(result) = matrix.result(player.id, opponent.id).filter(|x| x.is_played()) else { continue };
+            let report = reports.iter().rev().find(|x| same_match(matrix.double, (x.player, x.opponent), (player.id, opponent.id)));
+            matches.push(PlayedMatch{player: player.id, opponent: opponent.id, result: result.clone(),
+                reporter: report.map(|x| x.reporter), time: report.map(|x| x.time.clone())});
+        }
+    }
+    matches
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+//One line per played match, reporters outside the tournament by id
+pub fn csv(matrix: &Matrix, matches: &[PlayedMatch]) -> String {
+    let name = |id: PlayerId| matrix.player(id).map_or(id.to_string(), |x| x.name.clone());
+    let mut output = "player,opponent,score,reporter,time\n".to_string();
+    for played in matches{
+        let fields = [name(played.player), name(played.opponent), played.result.to_str(),
+            played.reporter.map(name).unwrap_or_default(), played.time.clone().unwrap_or_default()];
+        output += &fields.iter().map(|x| csv_field(x)).collect::<Vec<_>>().join(",");
+        output += "\n";
+    }
+    output
+}
+
+pub fn json(title: &str, matrix: &Matrix, matches: &[PlayedMatch], standings: &[Standing]) -> Value {
+    let rows: Vec<Vec<Option<String>>> = matrix.users.iter().map(|player| matrix.users.iter()
+        .map(|opponent| matrix.result(player.id, opponent.id).filter(|x| x.is_played()).map(|x| x.to_str())).collect()).collect();
+    let matches: Vec<Value> = matches.iter().map(|x| json!({"player": x.player, "opponent": x.opponent, "score": x.result.to_str(),
+        "reporter": x.reporter, "time": x.time})).collect();
+    json!({
+        "tournament": title,
+        "format": matrix.format.description(),
+        "double": matrix.double,
+        "swiss": matrix.swiss.is_some(),
+        "players": matrix.users,
+        "matrix": rows,
+        "matches": matches,
+        "standings": standings,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedMatch{
+    pub line: usize,
+    pub player: PlayerId,
+    pub opponent: PlayerId,
+    pub result: MatchResult,
+}
+
+//Splits CSV text into records with the line each starts on, quoted fields can span lines
+fn csv_records(text: &str) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let mut records = Vec::new();
+    let (mut fields, mut field) = (Vec::new(), String::new());
+    let (mut line, mut start, mut quoted) = (1, 1, false);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next(){
+        match c{
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            '"' if quoted || field.is_empty() => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {},
+            '\n' if !quoted => {
+                fields.push(std::mem::take(&mut field));
+                records.push((start, std::mem::take(&mut fields)));
+                line += 1;
+                start = line;
+            },
+            _ => {
+                if c == '\n' {line += 1}
+                field.push(c);
+            },
+        }
+    }
+    if quoted {
+        return Err(format!("line {}: unclosed quote", start));
+    }
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        records.push((start, fields));
+    }
+    Ok(records)
+}
+
+//Finds a player by id, mention or (case insensitive) name
+fn find_player(matrix: &Matrix, field: &str) -> Result<PlayerId, String> {
+    let id = field.trim_start_matches("<@").trim_start_matches('!').trim_end_matches('>');
+    if let Some(player) = id.parse().ok().and_then(|x| matrix.player(x)) {
+        return Ok(player.id);
+    }
+    let mut named = matrix.users.iter().filter(|x| x.name.to_lowercase() == field.to_lowercase());
+    match (named.next(), named.next()){
+        (Some(player), None) => Ok(player.id),
+        (Some(_), Some(_)) => Err(format!("more than one player is called {}, use their id", field)),
+        _ => Err(format!("no player {} in the tournament", field)),
+    }
+}
+
+//Reads `player,opponent,score` rows, returning every problem found so they can all be reported at once
+pub fn import_csv(matrix: &Matrix, text: &str) -> (Vec<ImportedMatch>, Vec<String>) {
+    let records = match csv_records(text){
+        Ok(records) => records,
+        Err(error) => return (Vec::new(), vec![error]),
+    };
+    let mut matches: Vec<ImportedMatch> = Vec::new();
+    let mut errors = Vec::new();
+    for (line, fields) in records{
+        let fields: Vec<&str> = fields.iter().map(|x| x.trim()).collect();
+        if fields.iter().all(|x| x.is_empty()) || (line == 1 && fields[0].eq_ignore_ascii_case("player")) { continue }
+        let [player, opponent, score, ..] = fields[..] else {
+            errors.push(format!("line {}: expected a player, an opponent and a score", line));
+            continue
+        };
+        let parsed = (|| {
+            let player = find_player(matrix, player)?;
+            let opponent = find_player(matrix, opponent)?;
+            if player == opponent {
+                return Err("a player can't play themselves".to_string());
+            }
+            if let Some(withdrawn) = [player, opponent].into_iter().find(|x| matrix.withdrawn.contains(x)) {
+                return Err(format!("{} has withdrawn", matrix.name_of(withdrawn)));
+            }
+            let result = MatchResult::get(score).filter(|x| matrix.format.allows(x))
+                .ok_or_else(|| format!("{} is not a valid score for a {} match", score, matrix.format.description()))?;
+            if let Some(earlier) = matches.iter().find(|x| same_match(matrix.double, (x.player, x.opponent), (player, opponent))) {
+                return Err(format!("{} against {} is already on line {}", matrix.name_of(player), matrix.name_of(opponent), earlier.line));
+            }
+            Ok(ImportedMatch{line, player, opponent, result})
+        })();
+        match parsed{
+            Ok(imported) => matches.push(imported),
+            Err(error) => errors.push(format!("line {}: {}", line, error)),
+        }
+    }
+    (matches, errors)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::matrix::tests::players;
+    use crate::standings;
+
+    fn report(player: u64, opponent: u64, reporter: u64, time: &str) -> Report {
+        Report{player: PlayerId(player), opponent: PlayerId(opponent), reporter: PlayerId(reporter), time: time.to_string()}
+    }
+
+    #[test]
+    fn single_round_robin_matches_are_listed_once_with_their_latest_report() {
+        let mut matrix = Matrix::new(players(3), Default::default(), Vec::new(), None, false);
+        matrix.set_result(PlayerId(3), PlayerId(1), MatchResult::Score(2, 1)).unwrap();
+        matrix.set_result(PlayerId(2), PlayerId(3), MatchResult::Score(0, 2)).unwrap();
+        let reports = [report(3, 1, 3, "a"), report(1, 3, 1, "b"), report(3, 2, 9, "c")];
+        let matches = played_matches(&matrix, &reports);
+        assert_eq!(matches, vec![
+            PlayedMatch{player: PlayerId(1), opponent: PlayerId(3), result: MatchResult::Score(1, 2), reporter: Some(PlayerId(1)), time: Some("b".to_string())},
+            PlayedMatch{player: PlayerId(2), opponent: PlayerId(3), result: MatchResult::Score(0, 2), reporter: Some(PlayerId(9)), time: Some("c".to_string())},
+        ]);
+        assert_eq!(csv(&matrix, &matches), "player,opponent,score,reporter,time\nplayer1,player3,1-2,player1,b\nplayer2,player3,0-2,9,c\n");
+    }
+
+    #[test]
+    fn double_round_robin_keeps_both_legs() {
+        let mut matrix = Matrix::new(players(2), Default::default(), Vec::new(), None, true);
+        matrix.set_result(PlayerId(1), PlayerId(2), MatchResult::Score(2, 0)).unwrap();
+        matrix.set_result(PlayerId(2), PlayerId(1), MatchResult::Score(2, 1)).unwrap();
+        let matches = played_matches(&matrix, &[report(2, 1, 2, "a")]);
+        assert_eq!(matches.len(), 2);
+        assert_eq!((matches[0].result.clone(), matches[0].reporter), (MatchResult::Score(2, 0), None));
+        assert_eq!((matches[1].result.clone(), matches[1].reporter), (MatchResult::Score(2, 1), Some(PlayerId(2))));
+    }
+
+    #[test]
+    fn awkward_names_are_quoted() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn exported_csv_imports_back() {
+        let mut matrix = Matrix::new(players(3), Default::default(), Vec::new(), None, false);
+        matrix.users[0].name = "Smith, \"Jo\"".to_string();
+        matrix.set_result(PlayerId(1), PlayerId(2), MatchResult::Score(2, 1)).unwrap();
+        matrix.set_result(PlayerId(3), PlayerId(2), MatchResult::Score(0, 2)).unwrap();
+        let matches = played_matches(&matrix, &[report(1, 2, 1, "a")]);
+        let (imported, errors) = import_csv(&matrix, &csv(&matrix, &matches));
+        assert!(errors.is_empty(), "{:?}", errors);
+        let pairs: Vec<_> = imported.iter().map(|x| (x.player, x.opponent, x.result.clone())).collect();
+        assert_eq!(pairs, matches.into_iter().map(|x| (x.player, x.opponent, x.result)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn import_reports_every_bad_row() {
+        let matrix = Matrix::new(players(3), Default::default(), Vec::new(), None, false);
+        let text = "player,opponent,score\nplayer1,<@2>,2-0\nPLAYER3,1,1-2\nplayer2,player2,2-0\ndave,player1,2-0\nplayer2,player3,3-0\nplayer2\n\nplayer2,player1,1-2\n";
+        let (imported, errors) = import_csv(&matrix, text);
+        assert_eq!(imported.len(), 2);
+        assert_eq!(errors, vec![
+            "line 4: a player can't play themselves",
+            "line 5: no player dave in the tournament",
+            "line 6: 3-0 is not a valid score for a best of 3 match",
+            "line 7: expected a player, an opponent and a score",
+            "line 9: player2 against player1 is already on line 2",
+        ]);
+        assert_eq!(import_csv(&matrix, "player1,player2,2-0\n\"open,2,2-0\n").1, vec!["line 2: unclosed quote"]);
+        let (imported, errors) = import_csv(&matrix, "player1,<@2>,2-0\r\nPLAYER3,1,1-2");
+        assert!(errors.is_empty());
+        assert_eq!(imported[1], ImportedMatch{line: 2, player: PlayerId(3), opponent: PlayerId(1), result: MatchResult::Score(1, 2)});
+    }
+
+    #[test]
+    fn json_has_every_square_and_standing() {
+        let mut matrix = Matrix::new(players(3), Default::default(), Vec::new(), None, false);
+        matrix.set_result(PlayerId(1), PlayerId(2), MatchResult::Score(2, 1)).unwrap();
+        let matches = played_matches(&matrix, &[]);
+        let value = json("Test", &matrix, &matches, &standings::compute(&matrix));
+        assert_eq!(value["matrix"], json!([[null, "2-1", null], ["1-2", null, null], [null, null, null]]));
+        assert_eq!(value["standings"][0]["user"], "1");
+        assert_eq!(value["standings"].as_array().unwrap().len(), 3);
+        assert_eq!(value["matches"][0]["reporter"], Value::Null);
+    }
+}
@@ -0,0 +1,760 @@
+//! Whole command flows run against the in-memory Discord fake
+use std::sync::atomic::AtomicU64;
+
+use serde_json::{json, Value};
+
+use super::*;
+use crate::discord::fake::FakeDiscord;
+
+const GUILD: GuildId = GuildId::new(1);
+const CHANNEL: ChannelId = ChannelId::new(10);
+//Has moderator permissions, everyone else is a plain member
+const ORGANIZER: u64 = 99;
+const MODERATE_MEMBERS: &str = "1099511627776";
+
+enum Opt<'a>{
+    Str(&'a str),
+    Int(i64),
+    Bool(bool),
+    User(u64),
+    Role(u64),
+    //An attached file, downloadable from file_url
+    File(&'a str),
+    Sub(Vec<(&'a str, Opt<'a>)>),
+}
+
+fn user_json(id: u64) -> Value{
+    json!({"id": id.to_string(), "username": format!("user{}", id), "discriminator": "0", "global_name": null, "avatar": null})
+}
+
+fn file_url(filename: &str) -> String{
+    format!("https://cdn.test/{}", filename)
+}
+
+static NEXT_INTERACTION: AtomicU64 = AtomicU64::new(500);
+
+//Option values as Discord sends them, with any users, roles and files added to the resolved data
+fn options_json(options: Vec<(&str, Opt<'_>)>, resolved: &mut [serde_json::Map<String, Value>; 4]) -> Vec<Value>{
+    options.into_iter().map(|(name, value)| match value {
+        Opt::Str(x) => json!({"name": name, "type": 3, "value": x}),
+        Opt::Int(x) => json!({"name": name, "type": 4, "value": x}),
+        Opt::Bool(x) => json!({"name": name, "type": 5, "value": x}),
+        Opt::User(x) => {
+            resolved[0].insert(x.to_string(), user_json(x));
+            resolved[1].insert(x.to_string(), json!({"roles": [], "joined_at": "2024-01-01T00:00:00Z", "flags": 0}));
+            json!({"name": name, "type": 6, "value": x.to_string()})
+        },
+        Opt::Role(x) => {
+            resolved[3].insert(x.to_string(), json!({"id": x.to_string(), "name": format!("role{}", x), "color": 0, "hoist": false,
+                "position": 1, "permissions": "0", "managed": false, "mentionable": true}));
+            json!({"name": name, "type": 8, "value": x.to_string()})
+        },
+        Opt::File(filename) => {
+            let id = (resolved[2].len()+1).to_string();
+            resolved[2].insert(id.clone(), json!({"id": id, "filename": filename, "size": 0, "url": file_url(filename), "proxy_url": file_url(filename)}));
+            json!({"name": name, "type": 11, "value": id})
+        },
+        Opt::Sub(options) => json!({"name": name, "type": 1, "options": options_json(options, resolved)}),
+    }).collect()
+}
+
+fn command(name: &str, user: u64, channel: ChannelId, options: Vec<(&str, Opt<'_>)>) -> CommandInteraction{
+    let mut resolved = Default::default();
+    let options = options_json(options, &mut resolved);
+    let [users, members, attachments, roles] = resolved;
+    let permissions = if user == ORGANIZER {MODERATE_MEMBERS} else {"0"};
+    let id = NEXT_INTERACTION.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    serde_json::from_value(json!({
+        "id": id.to_string(),
+        "application_id": "2",
+        "type": 2,
+        "data": {"id": "3", "name": name, "type": 1, "options": options, "resolved": {"users": users, "members": members, "roles": roles, "attachments": attachments}},
+        "guild_id": GUILD.to_string(),
+        "channel_id": channel.to_string(),
+        "member": {"user": user_json(user), "roles": [], "joined_at": "2024-01-01T00:00:00Z", "deaf": false, "mute": false, "flags": 0,
+            "permissions": permissions},
+        "token": "token",
+        "version": 1,
+        "locale": "en-GB",
+        "entitlements": [],
+    })).unwrap()
+}
+
+fn component(custom_id: &str, user: u64, channel: ChannelId, message: MessageId) -> ComponentInteraction{
+    let id = NEXT_INTERACTION.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    let permissions = if user == ORGANIZER {MODERATE_MEMBERS} else {"0"};
+    serde_json::from_value(json!({
+        "id": id.to_string(),
+        "application_id": "2",
+        "type": 3,
+        "data": {"custom_id": custom_id, "component_type": 2},
+        "guild_id": GUILD.to_string(),
+        "channel_id": channel.to_string(),
+        "member": {"user": user_json(user), "roles": [], "joined_at": "2024-01-01T00:00:00Z", "deaf": false, "mute": false, "flags": 0,
+            "permissions": permissions},
+        "message": {"id": message.to_string(), "channel_id": channel.to_string(), "author": user_json(2), "content": "", "timestamp": "2024-01-01T00:00:00Z",
+            "edited_timestamp": null, "tts": false, "mention_everyone": false, "mentions": [], "mention_roles": [], "attachments": [], "embeds": [],
+            "pinned": false, "type": 0},
+        "token": "token",
+        "version": 1,
+        "locale": "en-GB",
+        "entitlements": [],
+    })).unwrap()
+}
+
+struct Bot{
+    handler: Handler,
+    discord: FakeDiscord,
+    data_dir: std::path::PathBuf,
+}
+impl Bot{
+    fn new() -> Self{
+        static NEXT_DIR: AtomicU64 = AtomicU64::new(0);
+        let data_dir = std::env::temp_dir().join(format!("rema-bot-test-{}-{}", std::process::id(), NEXT_DIR.fetch_add(1, std::sync::atomic::Ordering::SeqCst)));
+        let discord = FakeDiscord::new();
+        for (id, name) in [(1, "Alice"), (2, "Bob"), (3, "Carol"), (ORGANIZER, "Organizer")]{
+            discord.add_member(UserId::new(id), name);
+        }
+        Bot{handler: Handler::new(Storage::new(data_dir.clone())), discord, data_dir}
+    }
+    fn thread(&self) -> ChannelId{
+        self.discord.threads().last().expect("no thread created").0
+    }
+    //Runs /begin, /add and /create for Alice, Bob and Carol
+    async fn create(&self, begin_options: Vec<(&str, Opt<'_>)>){
+        let mut options = vec![("title", Opt::Str("Spring League")), ("cmd", Opt::Str("spring"))];
+        options.extend(begin_options);
+        self.handler.begin(&command("begin", ORGANIZER, CHANNEL, options)).await.unwrap();
+        let added = self.handler.add_users(&self.discord, &command("add", ORGANIZER, CHANNEL,
+            vec![("player", Opt::User(1)), ("player2", Opt::User(2)), ("player3", Opt::User(3))])).await.unwrap();
+        assert!(added.contains("Added 3 new players"), "{}", added);
+        self.handler.create(&self.discord, &command("create", ORGANIZER, CHANNEL, vec![])).await.unwrap();
+    }
+    async fn report(&self, user: u64, score: &str, opponent: u64) -> Result<String>{
+        let opponent = opponent.to_string();
+        self.handler.report_result_command(&self.discord, &command("spring", user, self.thread(), vec![("score", Opt::Str(score)), ("opponent", Opt::Str(&opponent))])).await
+    }
+    //Runs /reprocess in the thread, which Discord names after the tournament
+    async fn reprocess(&self) -> Result<String>{
+        let mut reprocess = command("reprocess", ORGANIZER, self.thread(), vec![]);
+        reprocess.channel = serde_json::from_value(json!({"id": self.thread().to_string(), "type": 11, "name": "Spring League", "permissions": "0"})).ok();
+        self.handler.reprocess(&self.discord, &reprocess).await
+    }
+    fn grid(&self) -> String{
+        let posts = self.discord.messages_in(self.thread());
+        let matrix = self.handler.match_data.read(&GUILD, |_, x| x["spring"].mainposts.clone()).unwrap();
+        posts.iter().filter(|x| matrix.contains(&x.id)).map(|x| x.content.clone()).collect()
+    }
+}
+impl Drop for Bot{
+    fn drop(&mut self){
+        let _ = std::fs::remove_dir_all(&self.data_dir);
+    }
+}
+
+#[tokio::test]
+async fn round_robin_from_begin_to_end() {
+    let bot = Bot::new();
+    bot.create(vec![]).await;
+    assert_eq!(bot.discord.threads(), vec![(bot.thread(), "Spring League".to_string())]);
+    let posts = bot.discord.messages_in(bot.thread());
+    assert!(posts[0].content.starts_with("<@1> <@2> <@3>  Report your results here using the command /spring"));
+    assert!(bot.discord.command_names(GUILD).contains(&"spring".to_string()));
+    let opponents = &bot.discord.command(GUILD, "spring").unwrap()["options"][1]["choices"];
+    assert_eq!(opponents.as_array().unwrap().len(), 3);
+
+    bot.report(1, "2-1", 2).await.unwrap();
+    assert!(bot.grid().contains(":black_small_square: :waning_gibbous_moon: :cloud: 1/1 Alice"));
+    assert!(bot.grid().contains(":waxing_crescent_moon: :black_small_square: :cloud: 0/1 Bob"));
+    let announcement = bot.discord.messages_in(bot.thread()).pop().unwrap();
+    assert_eq!(announcement.content, "<@1> reports Alice 2-1 Bob");
+    assert_eq!(announcement.buttons, vec!["undo:0".to_string()]);
+
+    //Overwriting goes through the same command and is called out
+    bot.report(2, "2-0", 1).await.unwrap();
+    assert!(bot.discord.messages_in(bot.thread()).pop().unwrap().content.ends_with("overwriting previous result of 1-2"));
+    assert!(bot.grid().contains("0/1 Alice"));
+
+    let history = bot.handler.history(&command("history", 1, CHANNEL, vec![("tournament", Opt::Str("spring"))])).await.unwrap();
+    assert_eq!(history.lines().count(), 2);
+
+    bot.handler.end(&bot.discord, &command("end", ORGANIZER, CHANNEL, vec![("tournament", Opt::Str("spring"))])).await.unwrap();
+    let final_posts = bot.discord.messages_in(CHANNEL);
+    assert!(final_posts.iter().any(|x| x.content.contains("1/1 Bob")));
+    assert!(final_posts.last().unwrap().content.starts_with("Standings for Spring League"));
+    assert!(!bot.discord.command_names(GUILD).contains(&"spring".to_string()));
+}
+
+#[tokio::test]
+async fn reports_outside_the_rules_are_rejected() {
+    let bot = Bot::new();
+    bot.create(vec![("reporting", Opt::Str("participants"))]).await;
+    assert!(bot.report(1, "3-0", 2).await.unwrap_err().to_string().contains("not a valid score"));
+    assert!(bot.report(1, "2-0", 1).await.is_err());
+    //Carol isn't in the match, and the organizer can't report under participants only
+    let result = bot.handler.report_result_any(&bot.discord, &command("result", 3, bot.thread(),
+        vec![("score", Opt::Str("2-0")), ("opponent", Opt::User(2)), ("player", Opt::User(1))])).await;
+    assert!(result.unwrap_err().to_string().contains("only the two players"));
+    assert!(bot.grid().contains("0/0 Alice"));
+}
+
+#[tokio::test]
+async fn undo_restores_the_previous_result() {
+    let bot = Bot::new();
+    bot.create(vec![]).await;
+    bot.report(1, "2-0", 3).await.unwrap();
+    //Only the reporter or a moderator can undo
+    assert!(bot.handler.undo_command(&bot.discord, &command("undo", 3, bot.thread(), vec![])).await.is_err());
+    bot.handler.undo_command(&bot.discord, &command("undo", 1, bot.thread(), vec![])).await.unwrap();
+    assert!(bot.grid().contains(":black_small_square: :cloud: :cloud: 0/0 Alice"));
+    assert_eq!(bot.discord.messages_in(bot.thread()).pop().unwrap().content, "<@1> undid the report of Alice 2-0 Carol, restoring 0-0");
+}
+
+#[tokio::test]
+async fn moderators_correct_and_undo_bracket_matches_until_a_later_one_is_played() {
+    let bot = Bot::new();
+    bot.create(vec![]).await;
+    for (player, opponent) in [(1, 2), (1, 3), (2, 3)]{
+        bot.report(player, "2-0", opponent).await.unwrap();
+    }
+    bot.handler.playoffs(&bot.discord, &command("playoffs", ORGANIZER, CHANNEL, vec![("tournament", Opt::Str("spring")), ("cmd", Opt::Str("top")),
+        ("size", Opt::Int(3))])).await.unwrap();
+    let result = |user, score, opponent, player| command("result", user, bot.thread(), vec![("score", Opt::Str(score)), ("opponent", Opt::User(opponent)), ("player", Opt::User(player))]);
+    let opponent_of = |player| bot.handler.bracket_data.read(&GUILD, |_, x| x["top"].bracket.current_opponent(PlayerId(player))).unwrap();
+    bot.handler.report_result_any(&bot.discord, &result(2, "2-0", 3, 2)).await.unwrap();
+    //Only moderators can change a decided match
+    assert!(bot.handler.report_result_any(&bot.discord, &result(2, "0-2", 3, 2)).await.is_err());
+    bot.handler.report_result_any(&bot.discord, &result(ORGANIZER, "0-2", 3, 2)).await.unwrap();
+    assert_eq!(bot.discord.messages_in(bot.thread()).pop().unwrap().content, "<@99> corrects Bob 0-2 Carol");
+    assert_eq!(opponent_of(1), Some(PlayerId(3)));
+
+    assert!(bot.handler.undo_command(&bot.discord, &command("undo", 2, bot.thread(), vec![])).await.is_err());
+    bot.handler.undo_command(&bot.discord, &command("undo", ORGANIZER, bot.thread(), vec![])).await.unwrap();
+    assert_eq!(bot.discord.messages_in(bot.thread()).pop().unwrap().content, "<@99> undid the report of (2) Bob 0-2 **(3) Carol**");
+    assert_eq!(opponent_of(1), None);
+    assert_eq!(opponent_of(2), Some(PlayerId(3)));
+
+    bot.handler.report_result_any(&bot.discord, &result(2, "2-1", 3, 2)).await.unwrap();
+    bot.handler.report_result_any(&bot.discord, &result(1, "2-0", 2, 1)).await.unwrap();
+    let corrected = bot.handler.report_result_any(&bot.discord, &result(ORGANIZER, "0-2", 3, 2)).await;
+    assert!(corrected.unwrap_err().to_string().contains("a later match has already been played"));
+}
+
+#[tokio::test]
+async fn confirmation_holds_reports_until_disputed_and_resolved() {
+    let bot = Bot::new();
+    bot.create(vec![("confirm", Opt::Bool(true))]).await;
+    bot.report(1, "2-1", 2).await.unwrap();
+    assert!(bot.grid().contains(":black_small_square: :hourglass: :cloud: 0/0 Alice"));
+    let pending = bot.discord.messages_in(bot.thread()).pop().unwrap();
+    assert!(pending.content.contains("waiting for confirmation from <@2>"));
+    assert_eq!(pending.buttons.len(), 2);
+
+    bot.handler.dispute(&bot.discord, &command("dispute", 2, bot.thread(), vec![("opponent", Opt::User(1)), ("reason", Opt::Str("I won"))])).await.unwrap();
+    assert!(bot.grid().contains(":black_small_square: :warning: :cloud: 0/0 Alice"));
+    //The pending report's buttons are taken away
+    assert!(bot.discord.messages_in(bot.thread()).iter().find(|x| x.id == pending.id).unwrap().buttons.is_empty());
+    assert!(bot.report(1, "2-1", 2).await.is_err());
+
+    bot.handler.resolve(&bot.discord, &command("resolve", ORGANIZER, bot.thread(), vec![("player", Opt::User(2)), ("opponent", Opt::User(1)),
+        ("score", Opt::Str("2-1")), ("note", Opt::Str("stream replay"))])).await.unwrap();
+    assert!(bot.grid().contains("1/1 Bob"));
+    let history = bot.handler.history(&command("history", 1, CHANNEL, vec![("tournament", Opt::Str("spring"))])).await.unwrap();
+    assert!(history.trim_end().ends_with("- ruling: stream replay"), "{}", history);
+}
+
+#[tokio::test]
+async fn timed_out_reports_are_kept_when_their_message_cannot_be_edited() {
+    let bot = Bot::new();
+    bot.create(vec![("confirm", Opt::Bool(true)), ("confirm-timeout", Opt::Int(5))]).await;
+    bot.report(1, "2-1", 2).await.unwrap();
+    let lost = bot.discord.messages_in(bot.thread()).pop().unwrap();
+    bot.report(1, "2-0", 3).await.unwrap();
+    bot.discord.delete_message(bot.thread(), lost.id).await.unwrap();
+    bot.handler.match_data.update(&GUILD, |_, x| for report in &mut x.get_mut("spring").unwrap().pending{
+        report.timestamp = Timestamp::from_unix_timestamp(0).unwrap();
+    });
+
+    bot.handler.expire_pending(&bot.discord).await.unwrap();
+    let (pending, recorded) = bot.handler.match_data.read(&GUILD, |_, x| {
+        let matrix = &x["spring"];
+        (matrix.pending.iter().map(|x| x.opponent).collect::<Vec<_>>(), matrix.core.result(PlayerId(1), PlayerId(3)).cloned())
+    }).unwrap();
+    assert_eq!(pending, vec![PlayerId(2)]);
+    assert_eq!(recorded, Some(MatchResult::Score(2, 0)));
+}
+
+#[tokio::test]
+async fn timed_out_reports_that_cannot_be_recorded_are_dropped_with_a_notice() {
+    let bot = Bot::new();
+    bot.create(vec![("confirm", Opt::Bool(true)), ("confirm-timeout", Opt::Int(5))]).await;
+    bot.report(1, "2-1", 2).await.unwrap();
+    //Bob leaves without the report being cleared, so the result is rejected
+    bot.handler.match_data.update(&GUILD, |_, x| {
+        let matrix = x.get_mut("spring").unwrap();
+        matrix.core.withdrawn.insert(PlayerId(2));
+        matrix.pending[0].timestamp = Timestamp::from_unix_timestamp(0).unwrap();
+    });
+
+    bot.handler.expire_pending(&bot.discord).await.unwrap();
+    assert!(bot.handler.match_data.read(&GUILD, |_, x| x["spring"].pending.is_empty()).unwrap());
+    let notice = bot.discord.messages_in(bot.thread()).pop().unwrap().content;
+    assert!(notice.contains("could not be recorded and was dropped: Bob has withdrawn"), "{}", notice);
+    assert!(!bot.grid().contains(PENDING_ICON));
+}
+
+#[tokio::test]
+async fn a_confirmation_that_fails_leaves_the_report_to_confirm_again() {
+    let bot = Bot::new();
+    bot.create(vec![("confirm", Opt::Bool(true))]).await;
+    bot.report(1, "2-1", 2).await.unwrap();
+    let pending = bot.discord.messages_in(bot.thread()).pop().unwrap();
+    let interaction = pending.buttons[0].split_once(':').unwrap().1.to_string();
+    bot.discord.delete_message(bot.thread(), pending.id).await.unwrap();
+
+    let press = component(&format!("confirm:{}", interaction), 2, bot.thread(), pending.id);
+    assert!(bot.handler.confirm_button(&bot.discord, &press, &interaction, true).await.is_err());
+    assert_eq!(bot.handler.match_data.read(&GUILD, |_, x| x["spring"].pending.len()).unwrap(), 1);
+}
+
+#[tokio::test]
+async fn swiss_rounds_advance_as_matches_are_reported() {
+    let bot = Bot::new();
+    bot.create(vec![("mode", Opt::Str("swiss")), ("rounds", Opt::Int(3))]).await;
+    let pairings = bot.discord.messages_in(bot.thread()).pop().unwrap();
+    assert!(pairings.content.starts_with("Round 1 of 3 pairings:"));
+    assert!(pairings.content.contains("has a bye"));
+    let (player, opponent) = bot.handler.match_data.read(&GUILD, |_, x| {
+        x["spring"].core.swiss.as_ref().unwrap().current_round().iter().find_map(|(x, y)| y.map(|y| (x.0, y.0)))
+    }).unwrap().unwrap();
+    bot.report(player, "2-0", opponent).await.unwrap();
+    assert!(bot.discord.messages_in(bot.thread()).iter().any(|x| x.content.starts_with("Round 2 of 3 pairings:")));
+
+    //Round 2 was paired on that result, so it can only be corrected
+    let undo = bot.handler.undo_command(&bot.discord, &command("undo", player, bot.thread(), vec![])).await;
+    assert!(undo.unwrap_err().to_string().contains("the next round has been paired"));
+    bot.report(player, "0-2", opponent).await.unwrap();
+    bot.handler.undo_command(&bot.discord, &command("undo", player, bot.thread(), vec![])).await.unwrap();
+    let result = bot.handler.match_data.read(&GUILD, |_, x| x["spring"].core.result(PlayerId(player), PlayerId(opponent)).cloned()).unwrap();
+    assert_eq!(result, Some(MatchResult::Score(2, 0)));
+}
+
+#[tokio::test]
+async fn saved_tournaments_come_back_after_a_restart() {
+    let bot = Bot::new();
+    bot.create(vec![("double", Opt::Bool(true))]).await;
+    bot.report(1, "2-0", 2).await.unwrap();
+    bot.handler.persist(GUILD).await.unwrap();
+
+    let restarted = Bot{handler: Handler::new(Storage::new(bot.data_dir.clone())), discord: FakeDiscord::new(), data_dir: bot.data_dir.clone()};
+    restarted.handler.restore(&restarted.discord).await.unwrap();
+    assert!(restarted.discord.command_names(GUILD).contains(&"spring".to_string()));
+    let (double, result) = restarted.handler.match_data.read(&GUILD, |_, x| {
+        let matrix = &x["spring"].core;
+        (matrix.double, matrix.result(PlayerId(1), PlayerId(2)).cloned())
+    }).unwrap();
+    assert!(double);
+    assert_eq!(result, Some(MatchResult::Score(2, 0)));
+}
+
+#[tokio::test]
+async fn a_broken_save_file_is_set_aside_without_stopping_the_restore() {
+    let bot = Bot::new();
+    bot.create(vec![]).await;
+    bot.handler.persist(GUILD).await.unwrap();
+    std::fs::write(bot.data_dir.join("5.json"), "{not json").unwrap();
+    std::fs::write(bot.data_dir.join("0.json"), "{}").unwrap();
+
+    let restarted = Handler::new(Storage::new(bot.data_dir.clone()));
+    restarted.restore(&bot.discord).await.unwrap();
+    assert!(restarted.match_data.contains(&GUILD));
+    assert!(bot.data_dir.join("5.json.broken").exists());
+    assert!(!bot.data_dir.join("5.json").exists());
+}
+
+#[tokio::test]
+async fn reprocess_reads_the_thread_back() {
+    let bot = Bot::new();
+    bot.create(vec![("format", Opt::Str("bo5"))]).await;
+    bot.report(3, "3-1", 1).await.unwrap();
+    bot.handler.match_data.clear_async().await;
+
+    let result = bot.reprocess().await.unwrap();
+    assert!(result.contains("with 3 users"), "{}", result);
+    let (format, result) = bot.handler.match_data.read(&GUILD, |_, x| {
+        let matrix = &x["spring"].core;
+        (matrix.format, matrix.result(PlayerId(3), PlayerId(1)).cloned())
+    }).unwrap();
+    assert_eq!(format, MatchFormat::best_of(5).unwrap());
+    assert_eq!(result, Some(MatchResult::Score(3, 1)));
+}
+
+#[tokio::test]
+async fn reprocess_brings_a_legend_without_forfeits_up_to_date() {
+    let bot = Bot::new();
+    bot.create(vec![]).await;
+    let legend = bot.discord.messages_in(bot.thread()).into_iter().find(|x| x.content.starts_with(":cloud: match available")).unwrap();
+    let old = legend.content.lines().filter(|x| !x.contains("forfeited")).collect::<Vec<_>>().join("\n");
+    bot.discord.edit_message(bot.thread(), legend.id, EditMessage::new().content(old)).await.unwrap();
+    bot.handler.match_data.clear_async().await;
+
+    bot.reprocess().await.unwrap();
+    let legend = bot.discord.messages_in(bot.thread()).into_iter().find(|x| x.id == legend.id).unwrap();
+    assert!(legend.content.ends_with(":flag_white: match forfeited by a withdrawn player"), "{}", legend.content);
+}
+
+#[tokio::test]
+async fn fam_includes_or_excludes_player_groups() {
+    let bot = Bot::new();
+    bot.create(vec![]).await;
+    let group = |action, user| command("group", ORGANIZER, CHANNEL, vec![(action, Opt::Sub(vec![("name", Opt::Str("Strong")), ("player", Opt::User(user))]))]);
+    assert_eq!(bot.handler.player_groups(&group("add", 2)).await.unwrap(), "Added <@2> to strong");
+    assert!(bot.handler.player_groups(&group("add", 2)).await.is_err());
+    let list = bot.handler.player_groups(&command("group", ORGANIZER, CHANNEL, vec![("list", Opt::Sub(vec![]))])).await.unwrap();
+    assert_eq!(list, "strong: <@2>");
+
+    let bot = &bot;
+    let fam = |option| async move {
+        bot.handler.fam_pings(&bot.discord, &command("fam", 1, CHANNEL, vec![("tournament", Opt::Str("spring")), (option, Opt::Str("strong"))])).await.unwrap();
+        bot.discord.messages_in(CHANNEL).pop().unwrap().content
+    };
+    assert!(fam("exclude").await.ends_with("spring: <@3> "));
+    assert!(fam("include").await.ends_with("spring: <@2> "));
+    assert!(bot.handler.fam_pings(&bot.discord, &command("fam", 1, CHANNEL, vec![("exclude", Opt::Str("weak"))])).await.is_err());
+
+    //Groups outlive the tournament and are saved with the guild
+    bot.handler.player_groups(&group("remove", 2)).await.unwrap();
+    assert!(bot.handler.config_data.read(&GUILD, |_, x| x.groups.is_empty()).unwrap());
+    bot.handler.player_groups(&group("add", 3)).await.unwrap();
+    bot.handler.persist(GUILD).await.unwrap();
+    let restarted = Handler::new(Storage::new(bot.data_dir.clone()));
+    restarted.restore(&bot.discord).await.unwrap();
+    assert_eq!(restarted.config_data.read(&GUILD, |_, x| x.group("strong").unwrap().clone()).unwrap(), BTreeSet::from([PlayerId(3)]));
+}
+
+#[tokio::test]
+async fn themed_grids_read_back_in_their_theme() {
+    let bot = Bot::new();
+    let theme = |options| command("theme", ORGANIZER, CHANNEL, options);
+    assert!(bot.handler.set_theme(&theme(vec![("draw", Opt::Str(":cloud:"))])).await.is_err());
+    let preview = bot.handler.set_theme(&theme(vec![("preset", Opt::Str("circles")), ("wins", Opt::Str("<:w0:10> <:w1:11> <:w2:12> <:w3:13>")),
+        ("available", Opt::Str("⬜"))])).await.unwrap();
+    assert!(preview.contains("<:w0:10> match won 4-0") && preview.contains(":red_circle: match lost 0-4"), "{}", preview);
+
+    bot.create(vec![]).await;
+    bot.report(1, "2-1", 2).await.unwrap();
+    assert!(bot.grid().contains(":black_small_square: <:w1:11> ⬜ 1/1 Alice"), "{}", bot.grid());
+    assert!(bot.grid().contains(":orange_circle: :black_small_square: ⬜ 0/1 Bob"), "{}", bot.grid());
+
+    bot.handler.match_data.clear_async().await;
+    bot.reprocess().await.unwrap();
+    let result = bot.handler.match_data.read(&GUILD, |_, x| x["spring"].core.result(PlayerId(1), PlayerId(2)).cloned()).unwrap();
+    assert_eq!(result, Some(MatchResult::Score(2, 1)));
+}
+
+#[tokio::test]
+async fn a_tournament_theme_survives_losing_the_saved_state() {
+    let bot = Bot::new();
+    bot.create(vec![("theme", Opt::Str("hearts"))]).await;
+    bot.report(1, "2-1", 2).await.unwrap();
+    assert!(bot.discord.messages_in(bot.thread())[0].content.ends_with("\nTheme: hearts"));
+    //The server's theme no longer matches the tournament's
+    bot.handler.set_theme(&command("theme", ORGANIZER, CHANNEL, vec![("preset", Opt::Str("circles"))])).await.unwrap();
+
+    bot.handler.match_data.clear_async().await;
+    bot.reprocess().await.unwrap();
+    let (theme, result) = bot.handler.match_data.read(&GUILD, |_, x| (x["spring"].theme.clone(), x["spring"].core.result(PlayerId(1), PlayerId(2)).cloned())).unwrap();
+    assert_eq!(theme, Theme::preset("hearts").unwrap());
+    assert_eq!(result, Some(MatchResult::Score(2, 1)));
+}
+
+#[tokio::test]
+async fn grid_images_follow_the_reports() {
+    let bot = Bot::new();
+    bot.create(vec![("image", Opt::Bool(true))]).await;
+    let image = |bot: &Bot| bot.discord.messages_in(bot.thread()).into_iter().find(|x| !x.files.is_empty()).expect("no image posted");
+    let posted = image(&bot);
+    assert_eq!(posted.files[0].0, "grid.png");
+    assert!(posted.files[0].1.starts_with(b"\x89PNG"));
+
+    bot.report(1, "2-0", 2).await.unwrap();
+    let updated = image(&bot);
+    assert_eq!((updated.id, updated.edits), (posted.id, 1));
+    assert_ne!(updated.files[0].1, posted.files[0].1);
+
+    //Reading the thread back finds the image after the legend
+    bot.handler.match_data.clear_async().await;
+    bot.reprocess().await.unwrap();
+    bot.report(3, "2-1", 1).await.unwrap();
+    assert_eq!(image(&bot).edits, 2);
+    bot.handler.end(&bot.discord, &command("end", ORGANIZER, CHANNEL, vec![("tournament", Opt::Str("spring"))])).await.unwrap();
+    assert_eq!(bot.discord.messages_in(CHANNEL).iter().filter(|x| !x.files.is_empty()).count(), 1);
+}
+
+#[tokio::test]
+async fn export_attaches_the_matches_and_standings() {
+    let bot = Bot::new();
+    bot.create(vec![]).await;
+    bot.report(1, "2-1", 2).await.unwrap();
+    bot.report(3, "2-0", 1).await.unwrap();
+    let result = bot.handler.export(&bot.discord, &command("export", 2, CHANNEL, vec![("tournament", Opt::Str("spring"))])).await.unwrap();
+    assert_eq!(result, "Exported 2 played matches");
+
+    let files = bot.discord.messages_in(CHANNEL).pop().unwrap().files;
+    assert_eq!(files.iter().map(|x| x.0.as_str()).collect::<Vec<_>>(), vec!["spring.csv", "spring.json"]);
+    let csv = String::from_utf8(files[0].1.clone()).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "player,opponent,score,reporter,time");
+    assert!(lines[1].starts_with("Alice,Bob,2-1,Alice,"), "{}", csv);
+    assert!(lines[2].starts_with("Alice,Carol,0-2,Carol,"), "{}", csv);
+    let json: Value = serde_json::from_slice(&files[1].1).unwrap();
+    assert_eq!(json["tournament"], "Spring League");
+    assert_eq!(json["matrix"][2], json!(["2-0", null, null]));
+    assert_eq!(json["standings"][0]["user"], "3");
+}
+
+#[tokio::test]
+async fn import_checks_every_row_before_recording_any() {
+    let bot = Bot::new();
+    bot.create(vec![]).await;
+    let posts = bot.discord.messages_in(bot.thread()).len();
+    let import = |filename| command("import", ORGANIZER, CHANNEL, vec![("tournament", Opt::Str("spring")), ("file", Opt::File(filename))]);
+    bot.discord.upload(&file_url("bad.csv"), b"player,opponent,score\nAlice,Bob,2-1\nAlice,Dave,2-0\nBob,Carol,4-0\n");
+    let error = bot.handler.import(&bot.discord, &import("bad.csv")).await.unwrap_err().to_string();
+    assert_eq!(error, "Nothing was imported, bad.csv has 2 problems:\nline 3: no player Dave in the tournament\nline 4: 4-0 is not a valid score for a best of 3 match");
+    assert!(bot.grid().contains("0/0 Alice"));
+    assert!(bot.handler.import(&bot.discord, &command("import", 1, CHANNEL, vec![("tournament", Opt::Str("spring")), ("file", Opt::File("bad.csv"))])).await.is_err());
+
+    bot.discord.upload(&file_url("good.csv"), b"Alice,Bob,2-1\n<@3>,Bob,0-2\n");
+    assert_eq!(bot.handler.import(&bot.discord, &import("good.csv")).await.unwrap(), "Imported 2 results into Spring League");
+    assert!(bot.grid().contains("1/1 Alice") && bot.grid().contains("1/2 Bob"), "{}", bot.grid());
+    //Only the summary is posted, along with the grid edits
+    let thread = bot.discord.messages_in(bot.thread());
+    assert_eq!(thread.len(), posts+1);
+    assert_eq!(thread.last().unwrap().content, "<@99> imported 2 results from good.csv, 2 of them changed");
+    let history = bot.handler.history(&command("history", 1, CHANNEL, vec![("tournament", Opt::Str("spring"))])).await.unwrap();
+    assert_eq!(history.lines().count(), 2);
+}
+
+#[tokio::test]
+async fn players_join_and_leave_a_running_tournament() {
+    let bot = Bot::new();
+    bot.create(vec![]).await;
+    for (id, name) in [(4, "Dave"), (5, "Erin"), (6, "Frank"), (7, "Grace"), (8, "Heidi"), (9, "Ivan")]{
+        bot.discord.add_member(UserId::new(id), name);
+    }
+    let bot = &bot;
+    let change = |name: &'static str, user| async move {
+        bot.handler.change_players(&bot.discord, &command(name, ORGANIZER, CHANNEL, vec![("tournament", Opt::Str("spring")), ("player", Opt::User(user))]), name == "addplayer").await
+    };
+    bot.report(1, "2-0", 2).await.unwrap();
+    assert_eq!(change("addplayer", 4).await.unwrap(), "<@99> added Dave to the tournament");
+    assert!(change("addplayer", 4).await.is_err());
+    let posts = bot.discord.messages_in(bot.thread());
+    assert!(posts[0].content.starts_with("<@1> <@2> <@3> <@4>  Report your results here"));
+    assert!(bot.grid().contains(":cloud: :cloud: :cloud: :black_small_square: 0/0 Dave"), "{}", bot.grid());
+    assert_eq!(bot.discord.command(GUILD, "spring").unwrap()["options"][1]["choices"].as_array().unwrap().len(), 4);
+    bot.report(4, "2-1", 3).await.unwrap();
+
+    assert_eq!(change("removeplayer", 2).await.unwrap(), "<@99> removed Bob from the tournament, along with their 1 played matches");
+    assert!(bot.discord.messages_in(bot.thread())[0].content.starts_with("<@1> <@3> <@4>  Report"));
+    assert!(bot.grid().contains(":black_small_square: :cloud: :cloud: 0/0 Alice"), "{}", bot.grid());
+    assert!(!bot.grid().contains("Bob"));
+    assert!(bot.report(1, "2-0", 2).await.is_err());
+
+    //Enough players for a second grid message moves the grid to the end of the thread
+    for id in 5..=9{
+        change("addplayer", id).await.unwrap();
+    }
+    let mainposts = bot.handler.match_data.read(&GUILD, |_, x| x["spring"].mainposts.clone()).unwrap();
+    assert_eq!(mainposts.len(), 2);
+    let posts = bot.discord.messages_in(bot.thread());
+    assert_eq!(posts.iter().filter(|x| x.content.contains("match available")).count(), 1);
+    assert!(bot.grid().contains("1/1 Dave") && bot.grid().contains("0/0 Ivan"), "{}", bot.grid());
+
+    bot.handler.match_data.clear_async().await;
+    let result = bot.reprocess().await.unwrap();
+    assert!(result.contains("with 8 users"), "{}", result);
+    let result = bot.handler.match_data.read(&GUILD, |_, x| x["spring"].core.result(PlayerId(4), PlayerId(3)).cloned()).unwrap();
+    assert_eq!(result, Some(MatchResult::Score(2, 1)));
+    //The legend is found again, so it moves with the grid next time
+    let legend = bot.discord.messages_in(bot.thread()).into_iter().find(|x| x.content.contains("match available")).unwrap();
+    assert_eq!(bot.handler.match_data.read(&GUILD, |_, x| x["spring"].legend_post).unwrap(), Some(legend.id));
+}
+
+#[tokio::test]
+async fn withdrawn_players_forfeit_their_unplayed_matches() {
+    let bot = Bot::new();
+    bot.create(vec![("withdrawals", Opt::Str("forfeit"))]).await;
+    bot.report(1, "2-0", 2).await.unwrap();
+    let withdraw = |user| command("withdraw", ORGANIZER, CHANNEL, vec![("tournament", Opt::Str("spring")), ("player", Opt::User(user))]);
+    assert!(bot.handler.withdraw(&bot.discord, &command("withdraw", 3, CHANNEL, vec![("tournament", Opt::Str("spring")), ("player", Opt::User(1))])).await.is_err());
+    let announcement = bot.handler.withdraw(&bot.discord, &withdraw(1)).await.unwrap();
+    assert_eq!(announcement, "<@99> withdrew Alice from the tournament, their 1 unplayed matches are forfeited :flag_white:");
+    assert!(bot.handler.withdraw(&bot.discord, &withdraw(1)).await.is_err());
+    assert!(bot.grid().contains(":black_small_square: :full_moon: :flag_white: 1/2 ~~Alice~~"), "{}", bot.grid());
+    assert!(bot.grid().contains(":flag_white: :cloud: :black_small_square: 1/1 Carol"), "{}", bot.grid());
+    assert!(bot.report(3, "2-0", 1).await.unwrap_err().to_string().contains("Alice has withdrawn"));
+
+    //Nobody is pinged to play Alice, and Alice isn't looking for matches
+    bot.handler.fam_pings(&bot.discord, &command("fam", 3, CHANNEL, vec![("tournament", Opt::Str("spring"))])).await.unwrap();
+    assert!(bot.discord.messages_in(CHANNEL).pop().unwrap().content.ends_with("spring: <@2> "));
+    bot.handler.fam_pings(&bot.discord, &command("fam", 1, CHANNEL, vec![("tournament", Opt::Str("spring"))])).await.unwrap();
+    assert!(bot.discord.messages_in(CHANNEL).pop().unwrap().content.ends_with("spring: withdrawn"));
+
+    bot.handler.match_data.clear_async().await;
+    bot.reprocess().await.unwrap();
+    let (withdrawn, result) = bot.handler.match_data.read(&GUILD, |_, x| {
+        (x["spring"].core.withdrawn.clone(), x["spring"].core.result(PlayerId(1), PlayerId(3)).cloned())
+    }).unwrap();
+    assert_eq!(withdrawn, HashSet::from([PlayerId(1)]));
+    assert_eq!(result, Some(MatchResult::Forfeit(false)));
+}
+
+#[tokio::test]
+async fn reminders_go_to_players_with_matches_left_before_the_deadline() {
+    let bot = Bot::new();
+    bot.create(vec![]).await;
+    bot.report(1, "2-0", 2).await.unwrap();
+    bot.handler.findable(&bot.discord, &command("matchpings", 1, CHANNEL, vec![("tournament", Opt::Str("spring")), ("enable", Opt::Int(0))])).await.unwrap();
+    let deadline = |date: &str| command("deadline", ORGANIZER, CHANNEL, vec![("tournament", Opt::Str("spring")), ("date", Opt::Str(date))]);
+    assert!(bot.handler.set_deadline(&bot.discord, &deadline("next week")).await.is_err());
+    assert!(bot.handler.set_deadline(&bot.discord, &deadline("2020-01-01")).await.is_err());
+
+    //Two days away, so the 3 day reminder is due straight away
+    let soon = Timestamp::from_unix_timestamp(Timestamp::now().unix_timestamp() + 2*86400 + 3600).unwrap().to_string().replace('T', " ");
+    bot.handler.set_deadline(&bot.discord, &deadline(&soon[..16])).await.unwrap();
+    assert!(bot.grid().starts_with("Spring League\nDeadline <t:"), "{}", bot.grid());
+    let posts = bot.discord.messages_in(bot.thread()).len();
+    bot.handler.send_reminders(&bot.discord).await.unwrap();
+    let reminder = bot.discord.messages_in(bot.thread()).pop().unwrap().content;
+    assert!(reminder.starts_with("Reminder: Spring League ends <t:"), "{}", reminder);
+    assert!(reminder.ends_with("Matches still to play: Alice (1), <@2> (1), <@3> (2)"), "{}", reminder);
+    bot.handler.send_reminders(&bot.discord).await.unwrap();
+    assert_eq!(bot.discord.messages_in(bot.thread()).len(), posts+1);
+    assert_eq!(bot.handler.match_data.read(&GUILD, |_, x| x["spring"].reminders_sent.clone()).unwrap(), vec![7, 3]);
+
+    //The deadline line doesn't get in the way of reading the grid back
+    bot.reprocess().await.unwrap();
+    let result = bot.handler.match_data.read(&GUILD, |_, x| x["spring"].core.result(PlayerId(1), PlayerId(2)).cloned()).unwrap();
+    assert_eq!(result, Some(MatchResult::Score(2, 0)));
+}
+
+#[tokio::test]
+async fn organizers_set_up_separate_drafts_side_by_side() {
+    let bot = Bot::new();
+    let begin = |user, title, cmd| command("begin", user, CHANNEL, vec![("title", Opt::Str(title)), ("cmd", Opt::Str(cmd))]);
+    bot.handler.begin(&begin(ORGANIZER, "Spring League", "spring")).await.unwrap();
+    bot.handler.begin(&begin(98, "Autumn Cup", "autumn")).await.unwrap();
+    assert!(bot.handler.begin(&begin(98, "Spring Again", "spring")).await.is_err());
+
+    //Without a draft each organizer adds to their own
+    bot.handler.add_users(&bot.discord, &command("add", ORGANIZER, CHANNEL, vec![("player", Opt::User(1)), ("player2", Opt::User(2))])).await.unwrap();
+    bot.handler.add_users(&bot.discord, &command("add", 98, CHANNEL, vec![("player", Opt::User(3))])).await.unwrap();
+    assert!(bot.handler.add_users(&bot.discord, &command("add", 3, CHANNEL, vec![("player", Opt::User(1))])).await.is_err());
+    bot.handler.add_users(&bot.discord, &command("add", 3, CHANNEL, vec![("player", Opt::User(1)), ("draft", Opt::Str("autumn"))])).await.unwrap();
+    let players = |draft: &str| bot.handler.setup_data.read(&GUILD, |_, x| x[draft].users.iter().map(|x| x.id).collect::<Vec<_>>()).unwrap();
+    assert_eq!(players("spring"), vec![PlayerId(1), PlayerId(2)]);
+    assert_eq!(players("autumn"), vec![PlayerId(3), PlayerId(1)]);
+
+    bot.handler.persist(GUILD).await.unwrap();
+    let restarted = Handler::new(Storage::new(bot.data_dir.clone()));
+    restarted.restore(&bot.discord).await.unwrap();
+    assert_eq!(restarted.setup_data.read(&GUILD, |_, x| x.len()).unwrap(), 2);
+
+    let mut autocomplete = command("create", 3, CHANNEL, vec![("draft", Opt::Str("au"))]);
+    autocomplete.data.options[0].value = CommandDataOptionValue::Autocomplete{kind: CommandOptionType::String, value: "au".to_string()};
+    let choices = serde_json::to_value(bot.handler.autocomplete_draft(&autocomplete).await.unwrap()).unwrap();
+    assert_eq!(choices["choices"][0]["value"], "autumn", "{}", choices);
+
+    assert_eq!(bot.handler.cancel(&bot.discord, &command("cancel", 98, CHANNEL, vec![])).await.unwrap(), "Cancelled the setup of autumn");
+    bot.handler.create(&bot.discord, &command("create", ORGANIZER, CHANNEL, vec![])).await.unwrap();
+    assert!(bot.handler.setup_data.read(&GUILD, |_, _| ()).is_none());
+    assert!(bot.discord.command_names(GUILD).contains(&"spring".to_string()));
+}
+
+#[tokio::test]
+async fn add_takes_everyone_with_a_role_or_a_reaction() {
+    let bot = Bot::new();
+    bot.handler.begin(&command("begin", ORGANIZER, CHANNEL, vec![("title", Opt::Str("Spring League")), ("cmd", Opt::Str("spring"))])).await.unwrap();
+    assert!(bot.handler.add_users(&bot.discord, &command("add", ORGANIZER, CHANNEL, vec![])).await.is_err());
+    let players = RoleId::new(50);
+    bot.discord.give_role(UserId::new(1), players);
+    bot.discord.give_role(UserId::new(2), players);
+    let added = bot.handler.add_users(&bot.discord, &command("add", ORGANIZER, CHANNEL, vec![("role", Opt::Role(50))])).await.unwrap();
+    assert!(added.starts_with("Added 2 new players"), "{}", added);
+
+    let signup = bot.discord.say(CHANNEL, "React to play".to_string()).await.unwrap();
+    //User 7 reacted and then left the server
+    for user in [2, 3, 3, 7]{
+        bot.discord.react(signup, UserId::new(user));
+    }
+    let link = format!("https://discord.com/channels/{}/{}/{}", GUILD, CHANNEL, signup);
+    let added = bot.handler.add_users(&bot.discord, &command("add", ORGANIZER, ChannelId::new(11), vec![("reactions", Opt::Str(&link))])).await.unwrap();
+    assert!(added.starts_with("user7 isn't in the server.\nBob already included.\nAdded 1 new players. Full list of 3"), "{}", added);
+    assert!(bot.handler.add_users(&bot.discord, &command("add", ORGANIZER, CHANNEL, vec![("reactions", Opt::Str("not a link"))])).await.is_err());
+    assert!(bot.handler.add_users(&bot.discord, &command("add", ORGANIZER, CHANNEL, vec![("reactions", Opt::Str("12345"))])).await.is_err());
+    for zero in ["0", "https://discord.com/channels/1/0/5"]{
+        assert!(bot.handler.add_users(&bot.discord, &command("add", ORGANIZER, CHANNEL, vec![("reactions", Opt::Str(zero))])).await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn sign_up_fills_the_draft_with_a_waitlist_behind_the_cap() {
+    let bot = Bot::new();
+    bot.discord.add_member(UserId::new(4), "Dave");
+    bot.handler.begin(&command("begin", ORGANIZER, CHANNEL, vec![("title", Opt::Str("Spring League")), ("cmd", Opt::Str("spring"))])).await.unwrap();
+    assert!(bot.handler.signup(&bot.discord, &command("signup", ORGANIZER, CHANNEL, vec![("closes", Opt::Str("2020-01-01"))])).await.is_err());
+    bot.handler.signup(&bot.discord, &command("signup", ORGANIZER, CHANNEL, vec![("cap", Opt::Int(2))])).await.unwrap();
+    let message = bot.discord.messages_in(CHANNEL).pop().unwrap();
+    assert_eq!(message.buttons, vec!["signup:join:spring", "signup:leave:spring"]);
+    let (bot, id) = (&bot, message.id);
+    let press = |button: &'static str, user| async move {
+        bot.handler.signup_button(&bot.discord, &component(&format!("signup:{}", button), user, CHANNEL, id), button).await
+    };
+
+    press("join:spring", 1).await.unwrap();
+    assert!(press("join:spring", 1).await.is_err());
+    press("join:spring", 2).await.unwrap();
+    assert_eq!(press("join:spring", 3).await.unwrap(), "Spring League is full, you're number 1 on the waitlist");
+    press("join:spring", 4).await.unwrap();
+    let roster = bot.discord.messages_in(CHANNEL).iter().find(|x| x.id == message.id).unwrap().content.clone();
+    assert_eq!(roster, "Sign up for Spring League! 2/2 players\n1. Alice\n2. Bob\nWaitlist: Carol, Dave");
+
+    //Leaving lets the first player waiting in
+    press("leave:spring", 1).await.unwrap();
+    assert_eq!(bot.discord.messages_in(CHANNEL).pop().unwrap().content, "<@3> moved up from the waitlist into Spring League");
+    press("leave:spring", 4).await.unwrap();
+    assert!(press("leave:spring", 4).await.is_err());
+    let players = bot.handler.setup_data.read(&GUILD, |_, x| x["spring"].users.iter().map(|x| x.id).collect::<Vec<_>>()).unwrap();
+    assert_eq!(players, vec![PlayerId(2), PlayerId(3)]);
+
+    //The sweeper closes it once its time has passed
+    bot.handler.setup_data.update(&GUILD, |_, x| x.get_mut("spring").unwrap().signup.as_mut().unwrap().closes = Some(Timestamp::from_unix_timestamp(0).unwrap()));
+    bot.handler.close_signups(&bot.discord).await.unwrap();
+    assert!(bot.discord.messages_in(CHANNEL).into_iter().find(|x| x.id == message.id).unwrap().buttons.is_empty());
+    assert!(press("leave:spring", 2).await.is_err());
+
+    bot.handler.create(&bot.discord, &command("create", ORGANIZER, CHANNEL, vec![])).await.unwrap();
+    let closed = bot.discord.messages_in(CHANNEL).into_iter().find(|x| x.id == message.id).unwrap();
+    assert!(closed.buttons.is_empty());
+    assert!(closed.content.starts_with("Sign-up for Spring League is closed, 2/2 players\n1. Bob\n2. Carol"), "{}", closed.content);
+    assert!(press("join:spring", 1).await.is_err());
+}
+
+#[tokio::test]
+async fn a_long_roster_is_cut_short_to_fit_one_message() {
+    let bot = Bot::new();
+    bot.handler.begin(&command("begin", ORGANIZER, CHANNEL, vec![("title", Opt::Str("Open League")), ("cmd", Opt::Str("open"))])).await.unwrap();
+    bot.handler.signup(&bot.discord, &command("signup", ORGANIZER, CHANNEL, vec![])).await.unwrap();
+    let everyone = RoleId::new(60);
+    for id in 100..250{
+        bot.discord.add_member(UserId::new(id), &format!("A rather long display name {}", id));
+        bot.discord.give_role(UserId::new(id), everyone);
+    }
+    bot.handler.add_users(&bot.discord, &command("add", ORGANIZER, CHANNEL, vec![("role", Opt::Role(60))])).await.unwrap();
+    let roster = bot.discord.messages_in(CHANNEL).pop().unwrap().content;
+    assert!(roster.len() < 2000, "{}", roster.len());
+    assert!(roster.starts_with("Sign up for Open League! 150 players\n1. A rather long display name 100\n"), "{}", roster);
+    assert!(roster.ends_with(" more"), "{}", roster);
+}
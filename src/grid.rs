@@ -0,0 +1,315 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+use anyhow::{Result, Context as _, anyhow};
+use regex::Regex;
+
+use crate::matrix::{Matches, Matrix, Player, PlayerId};
+use crate::results::{MatchFormat, MatchResult};
+use crate::swiss;
+use crate::theme::Theme;
+
+pub const PENDING_ICON: &str = ":hourglass:";
+pub const DISPUTE_ICON: &str = ":warning:";
+pub const BYE_ICON: &str = ":free:";
+pub const MUTED_ICON: &str = ":no_bell:";
+pub const HOME_AND_AWAY_LEGEND: &str = "Each row holds that player's home matches";
+
+//Squares drawn over the recorded result, keyed like Matrix::results
+pub type Marks = HashMap<(PlayerId, PlayerId), &'static str>;
+
+pub fn render_grid(matrix: &Matrix, title: &str, theme: &Theme, marks: &Marks, show_fam: bool, message_count: usize) -> Result<Vec<String>> {
+    let users = &matrix.users;
+    let mut message_vec = Vec::new();
+    let mut message_str = title.to_string();
+    message_str.push('\n');
+    let lines_per_message = (users.len()+1).div_ceil(message_count);
+    let mut i = 0;
+    for y in users.iter(){
+        //Byes count as a won match
+        let wins = matrix.byes(y.id) + matrix.player_results(y.id).filter(|(_, x)| x.is_win()).count();
+        let matches = matrix.byes(y.id) + matrix.player_results(y.id).filter(|(_, x)| x.is_played()).count();
+        if let Some(swiss) = &matrix.swiss{
+            message_str.push_str(&swiss::render_row(matrix, swiss, y.id, theme, marks));
+        } else {
+            for x in users{
+                let result = matrix.results.get(&(x.id, y.id)).context("Grid render failed: users not found in matrix")?;
+                message_str.push_str(marks.get(&(x.id, y.id)).copied().unwrap_or_else(|| theme.icon(result, matrix.format)));
+                message_str.push(' ');
+            }
+        }
+        //Withdrawn players are struck through
+        let name = if matrix.withdrawn.contains(&y.id) {format!("~~{}~~", y.name)} else {y.name.clone()};
+        message_str.push_str(&format!("{}/{} {}{}\n", wins, matches, name, if show_fam && matrix.disabled_fam.contains(&y.id) {MUTED_ICON} else {""}));
+        i += 1;
+        if i >= lines_per_message{
+            message_vec.push(message_str);
+            message_str = String::new();
+            i = 0;
+        }
+    }
+    //Swiss rows are by round, so there are no columns to label
+    for user in users.iter().filter(|_| matrix.swiss.is_none()){
+        let c = user.name.to_ascii_lowercase().chars().find(|x| x.is_ascii_alphanumeric());
+        let id_square = if let Some(c) = c{
+            if c.is_ascii_alphabetic(){
+                format!(":regional_indicator_{c}:")
+            } else {
+                format!(":number_{c}:")
+            }
+        } else {
+            String::from(":asterisk:")
+        };
+        message_str.push_str(&id_square);
+        message_str.push(' ');
+    }
+    message_str.push_str("\n_ _");
+    message_vec.push(message_str);
+    if message_vec.len() != message_count {
+        return Err(anyhow!("Grid render error: {} messages made but {} requested.", message_vec.len(), message_count));
+    }
+    Ok(message_vec)
+}
+
+pub fn legend(theme: &Theme, format: MatchFormat, swiss: bool, double: bool, confirm: bool) -> String {
+    let mut legend = theme.legend(format);
+    if confirm {
+        legend = legend + "\n" + PENDING_ICON + " awaiting confirmation";
+    }
+    if swiss {
+        legend = legend + "\n" + BYE_ICON + " bye";
+    }
+    if double {
+        legend = legend + "\n" + HOME_AND_AWAY_LEGEND;
+    }
+    legend
+}
+
+//Splits text into messages under the 2000 character limit, breaking between lines
+pub fn split_message(text: &str) -> Vec<String>{
+    let mut messages = Vec::new();
+    let mut message = String::new();
+    for line in text.lines(){
+        if !message.is_empty() && message.len() + line.len() > 1900 {
+            messages.push(std::mem::take(&mut message));
+        }
+        message.push_str(line);
+        message.push('\n');
+    }
+    if !message.is_empty() {
+        messages.push(message);
+    }
+    messages
+}
+
+//The first post of a results thread, which is enough to read the rest of the thread back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Intro{
+    pub players: Vec<PlayerId>,
+    pub shortname: String,
+    pub format: MatchFormat,
+    pub double: bool,
+    pub confirm: bool,
+    pub swiss_rounds: Option<u32>,
+    //The theme the grid is posted in, missing from threads made before it was recorded
+    pub theme: Option<Theme>,
+}
+impl Intro{
+    pub fn render(&self) -> String{
+        let mut intro = String::new();
+        for player in &self.players{
+            intro += &format!("<@{}> ", player);
+        }
+        intro += &format!(" Report your results here using the command /{} or /result ({}", self.shortname, self.format.description());
+        if self.double {
+            intro += ", home and away";
+        }
+        if self.confirm {
+            intro += ", confirmed by opponent";
+        }
+        intro.push(')');
+        if let Some(rounds) = self.swiss_rounds {
+            intro += &format!(" - {} round Swiss", rounds);
+        }
+        if let Some(theme) = &self.theme {
+            intro += &format!("\nTheme: {}", theme.to_text());
+        }
+        intro
+    }
+    pub fn parse(intro: &str) -> Result<Self>{
+        static RE_INTRO: LazyLock<Regex> = LazyLock::new(|| Regex::new(
+            r"^(.*) Report your results here using the command /([^ ]+) or /result(?: \(best of (\d+)(, draws allowed)?(, home and away)?(, confirmed by opponent)?\))?(?: - (\d+) round Swiss)?").unwrap());
+        static RE_USERID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<@(\d+)>").unwrap());
+        static RE_THEME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?m)^Theme: (.+)$").unwrap());
+        let content_match = RE_INTRO.captures(intro).context("intro message does not match expected")?;
+        let mut players = Vec::new();
+        for user_match in RE_USERID.captures_iter(&content_match[1]){
+            players.push(user_match[1].parse()?);
+        }
+        //Threads from before match formats were added are all best of 3
+        let mut format = match content_match.get(3) {
+            Some(games) => MatchFormat::best_of(games.as_str().parse()?)?,
+            None => MatchFormat::default(),
+        };
+        format.draws = content_match.get(4).is_some();
+        Ok(Intro{players, shortname: content_match[2].to_string(), format, double: content_match.get(5).is_some(), confirm: content_match.get(6).is_some(),
+            swiss_rounds: content_match.get(7).map(|x| x.as_str().parse()).transpose()?,
+            theme: RE_THEME.captures(intro).map(|x| Theme::from_text(&x[1])).transpose()?})
+    }
+}
+
+fn icon_regex(theme: &Theme) -> Regex {
+    let mut icons: Vec<&str> = theme.icons().chain([PENDING_ICON, DISPUTE_ICON]).collect();
+    //Longest first so an icon never matches only the start of another one
+    icons.sort_by_key(|x| std::cmp::Reverse(x.len()));
+    Regex::new(&icons.into_iter().map(regex::escape).collect::<Vec<_>>().join("|")).unwrap()
+}
+
+pub fn count_icons(text: &str, theme: &Theme) -> usize {
+    icon_regex(theme).find_iter(text).count()
+}
+
+#[derive(Debug)]
+pub struct ParsedGrid{
+    pub results: Matches,
+    pub disabled_fam: HashSet<PlayerId>,
+    pub withdrawn: HashSet<PlayerId>,
+    //Pending and disputed squares, whose recorded result isn't shown
+    pub hidden: Vec<(PlayerId, PlayerId)>,
+    //Icons left over after the grid, normally the legend's
+    pub extra_icons: usize,
+}
+
+//Reads the results out of a rendered round-robin grid, the players must be in row order
+pub fn parse_grid(text: &str, users: &[Player], format: MatchFormat, theme: &Theme) -> Result<ParsedGrid> {
+    let mut results = HashMap::new();
+    let mut disabled_fam = HashSet::new();
+    let mut withdrawn_players = HashSet::new();
+    let mut hidden = Vec::new();
+    let icons = icon_regex(theme);
+    let mut matrix_match = icons.find_iter(text);
+    for y in users{
+        let struck = format!("~~{}~~", y.name);
+        let withdrawn = text.contains(&struck);
+        if withdrawn {
+            withdrawn_players.insert(y.id);
+        }
+        for x in users{
+            let icon = matrix_match.next().context(format!("Unable to find match results matrix content for {},{}", x.name, y.name))?.as_str();
+            let result = if icon == PENDING_ICON || icon == DISPUTE_ICON {
+                hidden.push((x.id, y.id));
+                MatchResult::NotPlayed
+            } else {
+                let result = theme.result(icon, format).context(format!("Unknown result {} for {},{}", icon, x.name, y.name))?;
+                if withdrawn && result == MatchResult::Forfeit(true) {MatchResult::Forfeit(false)} else {result}
+            };
+            results.insert((x.id, y.id), result);
+        }
+        if text.contains(&format!("{}{}", if withdrawn {&struck} else {&y.name}, MUTED_ICON)) {
+            disabled_fam.insert(y.id);
+        }
+    }
+    Ok(ParsedGrid{results, disabled_fam, withdrawn: withdrawn_players, hidden, extra_icons: matrix_match.count()})
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::matrix::{Withdrawal, tests::{matrix, players}};
+    use crate::theme::{PRESETS, tests::custom};
+    use proptest::prelude::*;
+
+    #[test]
+    fn grid_has_a_row_per_player_and_a_letter_footer() {
+        let mut matrix = matrix(3);
+        matrix.set_result(PlayerId(1), PlayerId(2), MatchResult::Score(2, 0)).unwrap();
+        let grid = render_grid(&matrix, "Title", &Theme::default(), &Marks::new(), true, 1).unwrap();
+        assert_eq!(grid.len(), 1);
+        let lines: Vec<&str> = grid[0].lines().collect();
+        assert_eq!(lines[0], "Title");
+        assert_eq!(lines[1], ":black_small_square: :full_moon: :cloud: 1/1 player1");
+        assert_eq!(lines[2], ":new_moon: :black_small_square: :cloud: 0/1 player2");
+        assert_eq!(lines[4], ":regional_indicator_p: :regional_indicator_p: :regional_indicator_p: ");
+    }
+
+    #[test]
+    fn marks_and_muted_players_show_in_the_grid() {
+        let mut matrix = matrix(2);
+        matrix.disabled_fam.insert(PlayerId(2));
+        let marks = Marks::from([((PlayerId(1), PlayerId(2)), PENDING_ICON)]);
+        let grid = render_grid(&matrix, "Title", &Theme::default(), &marks, true, 1).unwrap().join("");
+        assert!(grid.contains(":hourglass: :black_small_square: 0/0 player2:no_bell:"));
+        assert!(!render_grid(&matrix, "Title", &Theme::default(), &marks, false, 1).unwrap()[0].contains(":no_bell:"));
+    }
+
+    #[test]
+    fn grid_splits_over_the_requested_messages() {
+        let matrix = matrix(9);
+        for count in 1..=4{
+            assert_eq!(render_grid(&matrix, "Title", &Theme::default(), &Marks::new(), true, count).unwrap().len(), count);
+        }
+    }
+
+    #[test]
+    fn legend_lines_follow_the_options() {
+        let plain = legend(&Theme::default(), MatchFormat::default(), false, false, false);
+        let all = legend(&Theme::default(), MatchFormat::default(), true, true, true);
+        //Available, four scores, the diagonal and forfeits
+        assert_eq!(count_icons(&plain, &Theme::default()), 7);
+        assert!(all.contains(PENDING_ICON) && all.contains(":free:") && all.contains(HOME_AND_AWAY_LEGEND));
+    }
+
+    #[test]
+    fn legacy_intros_are_best_of_3() {
+        let intro = Intro::parse("<@12> <@34>  Report your results here using the command /league or /result").unwrap();
+        assert_eq!(intro.players, vec![PlayerId(12), PlayerId(34)]);
+        assert_eq!(intro.shortname, "league");
+        assert_eq!(intro.format, MatchFormat::default());
+        assert!(Intro::parse("hello").is_err());
+    }
+
+    #[test]
+    fn split_message_keeps_lines_whole() {
+        let text = (0..200).map(|x| format!("line {} {}", x, "x".repeat(40))).collect::<Vec<_>>().join("\n");
+        let messages = split_message(&text);
+        assert!(messages.len() > 1);
+        assert!(messages.iter().all(|x| x.len() < 2000));
+        assert_eq!(messages.concat().lines().count(), 200);
+    }
+
+    proptest!{
+        #[test]
+        fn intro_round_trips(ids in prop::collection::vec(1u64.., 0..12), first_to in 1u8..=MatchFormat::MAX_FIRST_TO, draws: bool, double: bool, confirm: bool, rounds in prop::option::of(1u32..10),
+                theme in 0..=PRESETS.len()+1) {
+            //One past the presets is the custom theme, two past is none for threads from before themes were recorded
+            let theme = PRESETS.get(theme).map(|x| Theme::preset(x).unwrap()).or((theme == PRESETS.len()).then(custom));
+            let intro = Intro{players: ids.into_iter().map(PlayerId).collect(), shortname: "cup".to_string(), format: MatchFormat{first_to, draws},
+                double, confirm, swiss_rounds: rounds, theme};
+            prop_assert_eq!(Intro::parse(&intro.render()).unwrap(), intro);
+        }
+
+        #[test]
+        fn grid_round_trips(reports in prop::collection::vec((1u64..=6, 1u64..=6, 0u8..=3), 0..30), muted in prop::collection::hash_set(1u64..=6, 0..3),
+                first_to in 1u8..=4, message_count in 1usize..=3, theme in 0..=PRESETS.len(), withdrawals in prop::collection::vec((1u64..=6, any::<bool>()), 0..3)) {
+            let theme = PRESETS.get(theme).map_or_else(custom, |x| Theme::preset(x).unwrap());
+            let mut matrix = matrix(6);
+            matrix.format = MatchFormat{first_to, draws: true};
+            for (x, y, games) in reports{
+                let result = if games >= first_to {MatchResult::Draw} else {MatchResult::Score(first_to, games)};
+                let _ = matrix.set_result(PlayerId(x), PlayerId(y), result);
+            }
+            for (player, forfeit) in withdrawals{
+                let _ = matrix.withdraw(PlayerId(player), if forfeit {Withdrawal::Forfeit} else {Withdrawal::Void});
+            }
+            matrix.disabled_fam = muted.into_iter().map(PlayerId).collect();
+            let legend = legend(&theme, matrix.format, false, false, false);
+            let text = render_grid(&matrix, "Title", &theme, &Marks::new(), true, message_count).unwrap().concat() + &legend;
+            let parsed = parse_grid(&text, &players(6), matrix.format, &theme).unwrap();
+            prop_assert_eq!(parsed.results, matrix.results);
+            prop_assert_eq!(parsed.disabled_fam, matrix.disabled_fam);
+            prop_assert_eq!(parsed.withdrawn, matrix.withdrawn);
+            prop_assert_eq!(parsed.extra_icons, count_icons(&legend, &theme));
+            prop_assert!(parsed.hidden.is_empty());
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use std::io::Write;
+
+use flate2::{Compression, write::ZlibEncoder};
+
+use crate::grid::{Marks, PENDING_ICON, DISPUTE_ICON};
+use crate::matrix::Matrix;
+use crate::results::{MatchFormat, MatchResult};
+
+type Rgb = [u8; 3];
+
+const CELL: usize = 40;
+const MARGIN: usize = 8;
+//Glyphs are drawn at twice the font's size
+const SCALE: usize = 2;
+const ADVANCE: usize = (GLYPH_WIDTH+1)*SCALE;
+const TEXT_HEIGHT: usize = GLYPH_HEIGHT*SCALE;
+//Longer names are cut short so the grid stays square
+const MAX_NAME: usize = 16;
+const NAME_SPACE: usize = MAX_NAME*ADVANCE + MARGIN;
+const TITLE_SPACE: usize = TEXT_HEIGHT + 2*MARGIN;
+const WINS_SPACE: usize = 6*ADVANCE;
+
+const BACKGROUND: Rgb = [255, 255, 255];
+const INK: Rgb = [33, 33, 33];
+const GRID_LINE: Rgb = [176, 190, 197];
+//Indexed like a theme's icons, clearest win and heaviest loss first
+const WIN_COLOURS: [Rgb; 4] = [[27, 94, 32], [56, 142, 60], [102, 187, 106], [165, 214, 167]];
+const LOSS_COLOURS: [Rgb; 4] = [[183, 28, 28], [211, 47, 47], [229, 115, 115], [239, 154, 154]];
+const DRAW_COLOUR: Rgb = [251, 192, 45];
+const AVAILABLE_COLOUR: Rgb = [236, 239, 241];
+const UNPLAYABLE_COLOUR: Rgb = [84, 110, 122];
+const PENDING_COLOUR: Rgb = [100, 181, 246];
+const DISPUTE_COLOUR: Rgb = [255, 152, 0];
+
+//Colour and label of a grid square, from the row player's point of view
+fn cell_style(result: &MatchResult, mark: Option<&str>, format: MatchFormat) -> (Rgb, String) {
+    match (mark, result){
+        (Some(PENDING_ICON), _) => (PENDING_COLOUR, "?".to_string()),
+        (Some(DISPUTE_ICON), _) => (DISPUTE_COLOUR, "!".to_string()),
+        (_, MatchResult::Score(x, y)) if x > y => (WIN_COLOURS[format.rung(*y)], result.to_str()),
+        (_, MatchResult::Score(x, _)) => (LOSS_COLOURS[format.rung(*x)], result.to_str()),
+        (_, MatchResult::Draw) => (DRAW_COLOUR, "D".to_string()),
+        (_, MatchResult::Forfeit(true)) => (WIN_COLOURS[0], "FF".to_string()),
+        (_, MatchResult::Forfeit(false)) => (LOSS_COLOURS[0], "FF".to_string()),
+        (_, MatchResult::NotPlayed) => (AVAILABLE_COLOUR, String::new()),
+        (_, MatchResult::Unplayable) => (UNPLAYABLE_COLOUR, String::new()),
+    }
+}
+
+//Draws the results matrix as a PNG
+pub fn render_png(matrix: &Matrix, title: &str, marks: &Marks) -> Vec<u8> {
+    let users = &matrix.users;
+    let grid_left = MARGIN + NAME_SPACE;
+    let grid_top = TITLE_SPACE + NAME_SPACE;
+    let width = grid_left + users.len()*CELL + WINS_SPACE + MARGIN;
+    let height = grid_top + users.len()*CELL + MARGIN;
+    let mut canvas = Canvas::new(width, height);
+    canvas.text(MARGIN, MARGIN, title, INK);
+    canvas.text(grid_left + users.len()*CELL + MARGIN, grid_top - TEXT_HEIGHT - MARGIN, "W/M", INK);
+    let text_offset = (CELL - TEXT_HEIGHT)/2;
+    for (i, player) in users.iter().enumerate(){
+        let name = short_name(&player.name);
+        canvas.text(grid_left - MARGIN - name.len()*ADVANCE, grid_top + i*CELL + text_offset, &name, INK);
+        //Withdrawn players are struck through
+        if matrix.withdrawn.contains(&player.id) {
+            canvas.fill(grid_left - MARGIN - name.len()*ADVANCE, grid_top + i*CELL + CELL/2 - SCALE/2, name.len()*ADVANCE, SCALE, INK);
+        }
+        canvas.text_upwards(grid_left + i*CELL + text_offset, grid_top - MARGIN, &name, INK);
+    }
+    for (row, y) in users.iter().enumerate(){
+        for (column, x) in users.iter().enumerate(){
+            let result = matrix.results.get(&(x.id, y.id)).unwrap_or(&MatchResult::NotPlayed);
+            let (colour, label) = cell_style(result, marks.get(&(x.id, y.id)).copied(), matrix.format);
+            let (left, top) = (grid_left + column*CELL, grid_top + row*CELL);
+            canvas.fill(left, top, CELL, CELL, GRID_LINE);
+            canvas.fill(left+1, top+1, CELL-2, CELL-2, colour);
+            canvas.text(left + (CELL - label.len()*ADVANCE)/2 + 1, top + text_offset, &label, contrasting(colour));
+        }
+        //Byes count as a won match, as in the text grid
+        let wins = matrix.byes(y.id) + matrix.player_results(y.id).filter(|(_, x)| x.is_win()).count();
+        let matches = matrix.byes(y.id) + matrix.player_results(y.id).filter(|(_, x)| x.is_played()).count();
+        canvas.text(grid_left + users.len()*CELL + MARGIN, grid_top + row*CELL + text_offset, &format!("{}/{}", wins, matches), INK);
+    }
+    canvas.png()
+}
+
+//The name in characters the font has, cut to fit
+fn short_name(name: &str) -> String {
+    let mut name: String = name.chars().map(|x| if x.is_ascii_graphic() || x == ' ' {x} else {'?'}).collect();
+    if name.len() > MAX_NAME {
+        name.truncate(MAX_NAME-1);
+        name.push('~');
+    }
+    name
+}
+
+fn contrasting(colour: Rgb) -> Rgb {
+    let luma = 299*colour[0] as u32 + 587*colour[1] as u32 + 114*colour[2] as u32;
+    if luma > 140_000 {INK} else {BACKGROUND}
+}
+
+struct Canvas{
+    width: usize,
+    height: usize,
+    pixels: Vec<Rgb>,
+}
+impl Canvas{
+    fn new(width: usize, height: usize) -> Self {
+        Canvas{width, height, pixels: vec![BACKGROUND; width*height]}
+    }
+    fn fill(&mut self, left: usize, top: usize, width: usize, height: usize, colour: Rgb) {
+        for y in top..(top+height).min(self.height){
+            for x in left..(left+width).min(self.width){
+                self.pixels[y*self.width + x] = colour;
+            }
+        }
+    }
+    fn glyph(c: char) -> &'static [u8; GLYPH_WIDTH] {
+        let index = (c as usize).wrapping_sub(' ' as usize);
+        FONT.get(index).unwrap_or(&FONT['?' as usize - ' ' as usize])
+    }
+    fn text(&mut self, left: usize, top: usize, text: &str, colour: Rgb) {
+        for (i, c) in text.chars().enumerate(){
+            for (column, bits) in Self::glyph(c).iter().enumerate(){
+                for row in (0..GLYPH_HEIGHT).filter(|row| bits >> row & 1 == 1){
+                    self.fill(left + i*ADVANCE + column*SCALE, top + row*SCALE, SCALE, SCALE, colour);
+                }
+            }
+        }
+    }
+    //Text turned a quarter anticlockwise, reading upwards from the bottom
+    fn text_upwards(&mut self, left: usize, bottom: usize, text: &str, colour: Rgb) {
+        for (i, c) in text.chars().enumerate(){
+            for (column, bits) in Self::glyph(c).iter().enumerate(){
+                for row in (0..GLYPH_HEIGHT).filter(|row| bits >> row & 1 == 1){
+                    let Some(top) = bottom.checked_sub(i*ADVANCE + (column+1)*SCALE) else { continue };
+                    self.fill(left + row*SCALE, top, SCALE, SCALE, colour);
+                }
+            }
+        }
+    }
+
+    fn png(&self) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut header = Vec::new();
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        //8 bit RGB, no interlacing
+        header.extend([8, 2, 0, 0, 0]);
+        chunk(&mut png, b"IHDR", &header);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        for row in self.pixels.chunks(self.width){
+            //Each scanline starts with its filter type, 0 for none
+            let mut scanline = vec![0];
+            scanline.extend(row.iter().flatten());
+            encoder.write_all(&scanline).expect("writing to a Vec can't fail");
+        }
+        chunk(&mut png, b"IDAT", &encoder.finish().expect("writing to a Vec can't fail"));
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend(kind);
+    png.extend(data);
+    png.extend(crc.finalize().to_be_bytes());
+}
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+//Classic 5x7 font for ' ' to '~', one byte per column with the lowest bit at the top
+const FONT: [[u8; GLYPH_WIDTH]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00], [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62], [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00], [0x00, 0x41, 0x22, 0x1C, 0x00], [0x14, 0x08, 0x3E, 0x08, 0x14], [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00], [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00], [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31],
+    [0x18, 0x14, 0x12, 0x7F, 0x10], [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x36, 0x36, 0x00, 0x00], [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14], [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3E], [0x7E, 0x11, 0x11, 0x11, 0x7E], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x09, 0x01], [0x3E, 0x41, 0x49, 0x49, 0x7A],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00], [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40], [0x7F, 0x02, 0x0C, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46], [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F], [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x3F, 0x40, 0x38, 0x40, 0x3F],
+    [0x63, 0x14, 0x08, 0x14, 0x63], [0x07, 0x08, 0x70, 0x08, 0x07], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04], [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78], [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7F], [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7E, 0x09, 0x01, 0x02], [0x0C, 0x52, 0x52, 0x52, 0x3E],
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3D, 0x00], [0x7F, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78], [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7C, 0x14, 0x14, 0x14, 0x08], [0x08, 0x14, 0x14, 0x18, 0x7C], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C], [0x3C, 0x40, 0x30, 0x40, 0x3C],
+    [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C], [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7F, 0x00, 0x00], [0x00, 0x41, 0x36, 0x08, 0x00], [0x08, 0x04, 0x08, 0x10, 0x08],
+];
+
+#[cfg(test)]
+mod tests{
+    use std::io::Read;
+
+    use flate2::read::ZlibDecoder;
+
+    use super::*;
+    use crate::matrix::{PlayerId, tests::matrix};
+
+    //Width, height and pixels of a PNG, checking every chunk's CRC
+    fn decode(png: &[u8]) -> (usize, usize, Vec<Rgb>) {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut rest = &png[8..];
+        let (mut width, mut height, mut compressed) = (0, 0, Vec::new());
+        while !rest.is_empty(){
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (kind, data) = (&rest[4..8], &rest[8..8+length]);
+            let mut crc = crc32fast::Hasher::new();
+            crc.update(&rest[4..8+length]);
+            assert_eq!(crc.finalize().to_be_bytes(), rest[8+length..12+length]);
+            match kind {
+                b"IHDR" => {
+                    width = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+                    height = u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize;
+                },
+                b"IDAT" => compressed.extend(data),
+                _ => {},
+            }
+            rest = &rest[12+length..];
+        }
+        let mut raw = Vec::new();
+        ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut raw).unwrap();
+        assert_eq!(raw.len(), height*(width*3+1));
+        let pixels = raw.chunks(width*3+1).flat_map(|row| row[1..].chunks(3).map(|x| [x[0], x[1], x[2]]).collect::<Vec<_>>()).collect();
+        (width, height, pixels)
+    }
+
+    #[test]
+    fn image_has_a_square_per_match_coloured_by_result() {
+        let mut matrix = matrix(3);
+        matrix.set_result(PlayerId(1), PlayerId(2), MatchResult::Score(2, 1)).unwrap();
+        let marks = Marks::from([((PlayerId(3), PlayerId(1)), PENDING_ICON)]);
+        let (width, height, pixels) = decode(&render_png(&matrix, "Title", &marks));
+        let (grid_left, grid_top) = (MARGIN + NAME_SPACE, TITLE_SPACE + NAME_SPACE);
+        assert_eq!((width, height), (grid_left + 3*CELL + WINS_SPACE + MARGIN, grid_top + 3*CELL + MARGIN));
+        //Corner pixels of each square miss the label
+        let square = |column: usize, row: usize| pixels[(grid_top + row*CELL + 2)*width + grid_left + column*CELL + 2];
+        assert_eq!(square(1, 0), WIN_COLOURS[1]);
+        assert_eq!(square(0, 1), LOSS_COLOURS[1]);
+        assert_eq!(square(2, 0), PENDING_COLOUR);
+        assert_eq!(square(0, 2), AVAILABLE_COLOUR);
+        assert_eq!(square(1, 1), UNPLAYABLE_COLOUR);
+    }
+
+    #[test]
+    fn names_are_cut_to_what_the_font_can_draw() {
+        assert_eq!(short_name("Zoë"), "Zo?");
+        assert_eq!(short_name("a very long player name"), "a very long pla~");
+        assert_eq!(short_name("a very long pla"), "a very long pla");
+    }
+}
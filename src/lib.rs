@@ -0,0 +1,10 @@
+//Tournament logic with no Discord dependency, the bot binary handles Discord
+pub mod results;
+pub mod theme;
+pub mod matrix;
+pub mod grid;
+pub mod image;
+pub mod standings;
+pub mod swiss;
+pub mod bracket;
+pub mod export;
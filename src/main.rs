@@ -4,165 +4,524 @@ use std::env;
 use serenity::all::*;
 use anyhow::{Result, Context as _, anyhow}; //overrides serenity Result
 
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap, BTreeSet};
 use std::collections::HashSet;
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use std::num::NonZeroU64;
 use scc::HashMap as SCCHashMap;
 use regex::Regex;
+use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum MatchResult{
-    NotPlayed, TwoZero, TwoOne, OneTwo, ZeroTwo, Unplayable
+use rema_bot::results::{MatchResult, MatchFormat, Leg};
+use rema_bot::matrix::{Matrix, Player, PlayerId, Withdrawal, same_match};
+use rema_bot::grid::{self, Marks, Intro, render_grid, split_message, PENDING_ICON, DISPUTE_ICON};
+use rema_bot::standings::{self, Tiebreaker};
+use rema_bot::swiss::{self, SwissState};
+use rema_bot::bracket::Bracket;
+use rema_bot::theme::Theme;
+use rema_bot::image;
+use rema_bot::export::{self, Report};
+
+mod storage;
+use storage::{Storage, GuildStateRef};
+mod discord;
+use discord::Discord;
+#[cfg(test)]
+mod flow_tests;
+
+fn player_id(user: UserId) -> PlayerId{
+    PlayerId(user.get())
 }
-impl MatchResult{
-    fn get(result: &str) -> Self {
-        match result{
-            "2-0"|":full_moon:" => Self::TwoZero,
-            "2-1"|":waning_gibbous_moon:" => Self::TwoOne,
-            "1-2"|":waxing_crescent_moon:" => Self::OneTwo,
-            "0-2"|":new_moon:" => Self::ZeroTwo,
-            "0-0"|":cloud:" => Self::NotPlayed,
-            _ => Self::Unplayable,
-        }
+fn user_id(player: PlayerId) -> UserId{
+    UserId::new(player.0)
+}
+
+fn score_option(format: MatchFormat, description: &str) -> CreateCommandOption {
+    let mut option = decisive_score_option(format, description);
+    if format.draws {
+        option = option.add_string_choice("Draw", MatchResult::Draw.to_str());
     }
-    fn render(&self) -> &str {
-        match self{
-            Self::NotPlayed => ":cloud:",
-            Self::TwoZero => ":full_moon:",
-            Self::TwoOne => ":waning_gibbous_moon:",
-            Self::OneTwo => ":waxing_crescent_moon:",
-            Self::ZeroTwo => ":new_moon:",
-            Self::Unplayable => ":black_small_square:"
+    option.add_string_choice("0-0 (No result)", "0-0")
+}
+//Only the win and loss scores, for matches that must have a winner
+fn decisive_score_option(format: MatchFormat, description: &str) -> CreateCommandOption {
+    let mut option = CreateCommandOption::new(CommandOptionType::String, "score", description).required(true);
+    for score in format.scores(){
+        let label = format!("{} ({})", score.to_str(), if score.is_win() {"Win"} else {"Loss"});
+        option = option.add_string_choice(label, score.to_str());
+    }
+    option
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum ReportPolicy{
+    ParticipantsOnly,
+    #[default]
+    ParticipantsOrOrganizers,
+    Anyone,
+}
+impl ReportPolicy{
+    fn get(name: &str) -> Option<Self> {
+        match name{
+            "participants" => Some(Self::ParticipantsOnly),
+            "participants-or-organizers" => Some(Self::ParticipantsOrOrganizers),
+            "anyone" => Some(Self::Anyone),
+            _ => None,
         }
     }
-    fn invert(&self) -> Self {
+    fn description(self) -> &'static str {
         match self{
-            Self::NotPlayed => Self::NotPlayed,
-            Self::TwoZero => Self::ZeroTwo,
-            Self::TwoOne => Self::OneTwo,
-            Self::OneTwo => Self::TwoOne,
-            Self::ZeroTwo => Self::TwoZero,
-            Self::Unplayable => Self::Unplayable
+            Self::ParticipantsOnly => "only the two players can report a match",
+            Self::ParticipantsOrOrganizers => "only the two players or an organizer can report a match",
+            Self::Anyone => "anyone can report a match",
         }
     }
-    fn to_str(&self) -> &str {
+    fn allows(self, reporter: UserId, is_organizer: bool, player: PlayerId, opponent: PlayerId) -> bool {
+        let is_participant = player_id(reporter) == player || player_id(reporter) == opponent;
         match self{
-            Self::NotPlayed => "0-0",
-            Self::TwoZero => "2-0",
-            Self::TwoOne => "2-1",
-            Self::OneTwo => "1-2",
-            Self::ZeroTwo => "0-2",
-            Self::Unplayable => "ERROR"
+            Self::ParticipantsOnly => is_participant,
+            Self::ParticipantsOrOrganizers => is_participant || is_organizer,
+            Self::Anyone => true,
         }
     }
 }
-
-type Matches = HashMap<(UserId, UserId), MatchResult>;
+//Discord's limit on choices for a command option
+const MAX_CHOICES: usize = 25;
+const GRID_IMAGE: &str = "grid.png";
+#[derive(Serialize, Deserialize)]
 struct MatchMatrixSetup{
     threadname: String,
     shortname: String,
-    users: Vec<LocalUser>,
+    users: Vec<Player>,
+    #[serde(default)]
+    format: MatchFormat,
+    #[serde(default = "standings::default_tiebreakers")]
+    tiebreakers: Vec<Tiebreaker>,
+    //Number of Swiss rounds to play, None for a round-robin
+    #[serde(default)]
+    swiss_rounds: Option<u32>,
+    //Every pair plays twice, once at each player's home
+    #[serde(default)]
+    double: bool,
+    #[serde(default)]
+    confirm: bool,
+    #[serde(default)]
+    confirm_timeout: Option<u32>,
+    #[serde(default)]
+    policy: ReportPolicy,
+    #[serde(default)]
+    audit_channel: Option<ChannelId>,
+    //Overrides the server's theme
+    #[serde(default)]
+    theme: Option<Theme>,
+    #[serde(default)]
+    image: bool,
+    #[serde(default)]
+    withdrawals: Withdrawal,
+    //Who ran /begin, their draft is picked when they don't name one
+    #[serde(default)]
+    organizer: Option<UserId>,
+    #[serde(default)]
+    signup: Option<Signup>,
+}
+//The message players join a draft from, with its Join and Leave buttons
+#[derive(Serialize, Deserialize)]
+struct Signup{
+    channel: ChannelId,
+    message: MessageId,
+    //Most players that can join, later ones go on the waitlist
+    #[serde(default)]
+    cap: Option<u32>,
+    #[serde(default)]
+    closes: Option<Timestamp>,
+    //Players waiting for a place, first come first served
+    #[serde(default)]
+    waitlist: Vec<Player>,
+    //Set once the buttons are gone, by the close time, /create or /cancel
+    #[serde(default)]
+    closed: bool,
+}
+impl MatchMatrixSetup{
+    fn is_full(&self) -> bool {
+        self.signup.as_ref().and_then(|x| x.cap).is_some_and(|cap| self.users.len() >= cap as usize)
+    }
+    fn roster(&self) -> String {
+        let Some(signup) = &self.signup else { return String::new() };
+        let count = match signup.cap {
+            Some(cap) => format!("{}/{} players", self.users.len(), cap),
+            None => format!("{} players", self.users.len()),
+        };
+        let mut lines = match (signup.closed, signup.closes) {
+            (true, _) => vec![format!("Sign-up for {} is closed, {}", self.threadname, count)],
+            (false, Some(closes)) => vec![format!("Sign up for {}! {}, closes <t:{}:f> (<t:{}:R>)", self.threadname, count, closes.unix_timestamp(), closes.unix_timestamp())],
+            (false, None) => vec![format!("Sign up for {}! {}", self.threadname, count)],
+        };
+        //Long lists are cut short so the message stays under Discord's 2000 character limit
+        let waitlist = cut_short(signup.waitlist.iter().map(|x| x.name.clone()).collect(), ", ", 300);
+        let players = cut_short(self.users.iter().enumerate().map(|(i, x)| format!("{}. {}", i+1, x.name)).collect(), "\n", 1900usize.saturating_sub(lines[0].len() + waitlist.len() + 20));
+        lines.extend([players].into_iter().filter(|x| !x.is_empty()));
+        if !waitlist.is_empty() {
+            lines.push(format!("Waitlist: {}", waitlist));
+        }
+        lines.join("\n")
+    }
+    fn signup_buttons(&self) -> Vec<CreateActionRow> {
+        if self.signup.as_ref().is_none_or(|x| x.closed) { return Vec::new() }
+        vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(format!("signup:join:{}", self.shortname)).label("Join").style(ButtonStyle::Success),
+            CreateButton::new(format!("signup:leave:{}", self.shortname)).label("Leave").style(ButtonStyle::Secondary),
+        ])]
+    }
+    //Failures only get printed since the message may have been deleted
+    async fn refresh_signup(&self, discord: &dyn Discord){
+        let Some(signup) = &self.signup else { return };
+        if let Err(why) = discord.edit_message(signup.channel, signup.message, EditMessage::new().content(self.roster()).components(self.signup_buttons())).await{
+            println!("Error updating the sign-up for {}: {why}", self.shortname);
+        }
+    }
 }
+#[derive(Serialize, Deserialize)]
 struct MatchMatrix{
     thread: ChannelId,
     threadname: String,
     mainposts: Vec<MessageId>,
-    users: Vec<LocalUser>,
-    results: Matches,
-    disabled_fam: HashSet<UserId>,
+    #[serde(flatten)]
+    core: Matrix,
+    #[serde(default)]
+    history: Vec<ReportEvent>,
+    //Reports from players only count once the other player confirms them
+    #[serde(default)]
+    confirm: bool,
+    //Minutes after which an unconfirmed report counts anyway
+    #[serde(default)]
+    confirm_timeout: Option<u32>,
+    #[serde(default)]
+    pending: Vec<PendingReport>,
+    #[serde(default)]
+    disputes: Vec<Dispute>,
+    //Whoever created the tournament, notified about disputes
+    #[serde(default)]
+    organizer: Option<UserId>,
+    #[serde(default)]
+    policy: ReportPolicy,
+    //Where rejected report attempts are logged
+    #[serde(default)]
+    audit_channel: Option<ChannelId>,
+    #[serde(default)]
+    theme: Theme,
+    //The icon explanation posted after the grid
+    #[serde(default)]
+    legend_post: Option<MessageId>,
+    //The grid drawn as a picture, posted after the legend
+    #[serde(default)]
+    image_post: Option<MessageId>,
+    #[serde(default)]
+    withdrawals: Withdrawal,
+    #[serde(default)]
+    deadline: Option<Timestamp>,
+    //Days before the deadline to remind players of their unplayed matches, largest first
+    #[serde(default)]
+    reminders: Vec<u32>,
+    //The reminders already posted for the current deadline
+    #[serde(default)]
+    reminders_sent: Vec<u32>,
 }
-struct Handler{
-    setup_data: SCCHashMap<GuildId, MatchMatrixSetup>,
-    match_data: SCCHashMap<GuildId, HashMap<String, MatchMatrix>>
-}
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct LocalUser{
-    name: String,
-    id: UserId,
-    user: User,
-}
-
-fn render_grid(users: &[LocalUser], results: &Matches, disabled_fam: &HashSet<UserId>, header: &str, message_count: usize) -> Result<Vec<String>> {
-    let mut message_vec = Vec::new();
-    let mut message_str = header.to_string();
-    message_str.push('\n');
-    let lines_per_message = (users.len()+1).div_ceil(message_count);
-    let mut i = 0;
-    for y in users.iter(){
-        let mut wins = 0;
-        let mut matches = 0;
-        for x in users{
-            let result = results.get(&(x.id, y.id)).context("Grid render failed: users not found in matrix")?;
-            message_str.push_str(result.render());
-            if [MatchResult::TwoZero, MatchResult::TwoOne].contains(result){
-                wins += 1;
-                matches += 1;
-            }
-            if [MatchResult::OneTwo, MatchResult::ZeroTwo].contains(result){
-                matches += 1;
-            }
-            message_str.push(' ');
-        }
-        message_str.push_str(&format!("{}/{} {}{}\n", wins, matches, &y.name, if disabled_fam.contains(&y.id) {":no_bell:"} else {""}));
-        i += 1;
-        if i >= lines_per_message{
-            message_vec.push(message_str);
-            message_str = String::new();
-            i = 0;
-        }
-    }
-    for user in users{
-        let c = user.name.to_ascii_lowercase().chars().find(|x| x.is_ascii_alphanumeric());
-        let id_square = if let Some(c) = c{
-            if c.is_ascii_alphabetic(){
-                format!(":regional_indicator_{c}:")
-            } else {
-                format!(":number_{c}:")
+impl MatchMatrix{
+    fn is_undone(&self, event_index: usize) -> bool{
+        self.history.iter().any(|x| x.undo_of == Some(event_index))
+    }
+    //Grid squares covered by a dispute or a pending report instead of the result
+    fn marks(&self) -> Marks {
+        let mut marks = Marks::new();
+        let both_ways = |marks: &mut Marks, player: PlayerId, opponent: PlayerId, icon| {
+            marks.insert((opponent, player), icon);
+            if !self.core.double {
+                marks.insert((player, opponent), icon);
             }
-        } else {
-            String::from(":asterisk:")
         };
-        message_str.push_str(&id_square);
-        message_str.push(' ');
+        for report in &self.pending{
+            both_ways(&mut marks, report.player, report.opponent, PENDING_ICON);
+        }
+        for dispute in &self.disputes{
+            both_ways(&mut marks, dispute.player, dispute.opponent, DISPUTE_ICON);
+        }
+        marks
     }
-    message_str.push_str("\n_ _");
-    message_vec.push(message_str);
-    if message_vec.len() != message_count {
-        return Err(anyhow!("Grid render error: {} messages made but {} requested.", message_vec.len(), message_count));
+    //Removes the pending reports for the same match as the given home player and opponent
+    fn take_pending(&mut self, player: PlayerId, opponent: PlayerId) -> Vec<PendingReport> {
+        let double = self.core.double;
+        self.pending.extract_if(.., |x| same_match(double, (x.player, x.opponent), (player, opponent))).collect()
     }
-    Ok(message_vec)
+    fn is_disputed(&self, player: PlayerId, opponent: PlayerId) -> bool {
+        self.disputes.iter().any(|x| same_match(self.core.double, (x.player, x.opponent), (player, opponent)))
+    }
+    fn is_organizer(&self, user: UserId, member: Option<&Member>) -> bool {
+        self.organizer == Some(user) || is_moderator(member)
+    }
+    fn legend(&self) -> String {
+        grid::legend(&self.theme, self.core.format, self.core.swiss.is_some(), self.core.double, self.confirm)
+    }
+    //The grid's title, with the deadline on its own line
+    fn header(&self) -> String {
+        match self.deadline {
+            Some(deadline) => format!("{}\nDeadline <t:{}:f> (<t:{}:R>)", self.threadname, deadline.unix_timestamp(), deadline.unix_timestamp()),
+            None => self.threadname.clone(),
+        }
+    }
+    fn render_grid(&self, show_fam: bool) -> Result<Vec<String>> {
+        render_grid(&self.core, &self.header(), &self.theme, &self.marks(), show_fam, self.mainposts.len())
+    }
+    //The latest reminder that is due and not yet posted
+    fn due_reminder(&self, now: i64) -> Option<u32> {
+        let deadline = self.deadline?.unix_timestamp();
+        if now >= deadline { return None }
+        self.reminders.iter().filter(|x| now >= deadline - **x as i64 * 86400).min().copied()
+            .filter(|x| !self.reminders_sent.contains(x))
+    }
+    fn render_png(&self) -> Vec<u8> {
+        image::render_png(&self.core, &self.threadname, &self.marks())
+    }
+}
+//Settings kept for a guild between tournaments
+#[derive(Default, Serialize, Deserialize)]
+struct GuildConfig{
+    //Named groups of players that /fam can include or exclude, e.g. "strong"
+    #[serde(default)]
+    groups: BTreeMap<String, BTreeSet<PlayerId>>,
+    //Emoji for new tournaments' grids, the default theme if unset
+    #[serde(default)]
+    theme: Option<Theme>,
+}
+impl GuildConfig{
+    fn is_empty(&self) -> bool{
+        self.groups.is_empty() && self.theme.is_none()
+    }
+    fn group(&self, name: &str) -> Result<&BTreeSet<PlayerId>>{
+        self.groups.get(&name.to_lowercase()).with_context(|| format!("no player group named {}", name))
+    }
+}
+#[derive(Serialize, Deserialize)]
+struct PlayoffBracket{
+    thread: ChannelId,
+    threadname: String,
+    mainposts: Vec<MessageId>,
+    #[serde(flatten)]
+    bracket: Bracket,
+    //Copied from the tournament the bracket was seeded from
+    #[serde(default)]
+    policy: ReportPolicy,
+    #[serde(default)]
+    audit_channel: Option<ChannelId>,
+}
+impl PlayoffBracket{
+    fn render(&self) -> Vec<String> {
+        self.bracket.render(&self.threadname, self.mainposts.len())
+    }
+}
+//A single result report, results are from the player's point of view
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ReportEvent{
+    reporter: UserId,
+    player: PlayerId,
+    opponent: PlayerId,
+    old_result: MatchResult,
+    new_result: MatchResult,
+    timestamp: Timestamp,
+    interaction: InteractionId,
+    //Set when this event reverts an earlier event in the history
+    #[serde(default)]
+    undo_of: Option<usize>,
+    //The ruling given when resolving a dispute
+    #[serde(default)]
+    note: Option<String>,
+}
+//A report waiting on the other player, stored from the home player's point of view
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingReport{
+    reporter: UserId,
+    player: PlayerId,
+    opponent: PlayerId,
+    result: MatchResult,
+    announcement: String,
+    timestamp: Timestamp,
+    interaction: InteractionId,
+    //The message with the Confirm and Dispute buttons
+    message: MessageId,
+}
+//A match whose result is contested, players can't report it until an organizer resolves it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Dispute{
+    player: PlayerId,
+    opponent: PlayerId,
+    raised_by: UserId,
+    reason: String,
+    timestamp: Timestamp,
+}
+//Shared with the background task that times out pending reports
+#[derive(Clone)]
+struct Handler{
+    //Drafts being set up, keyed by their command name
+    setup_data: Arc<SCCHashMap<GuildId, HashMap<String, MatchMatrixSetup>>>,
+    match_data: Arc<SCCHashMap<GuildId, HashMap<String, MatchMatrix>>>,
+    bracket_data: Arc<SCCHashMap<GuildId, HashMap<String, PlayoffBracket>>>,
+    config_data: Arc<SCCHashMap<GuildId, GuildConfig>>,
+    storage: Arc<Storage>,
+    sweeper_started: Arc<AtomicBool>,
+}
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+fn find_option<'a>(options: &'a [ResolvedOption<'a>], name: &str) -> Option<&'a ResolvedValue<'a>>{
+    options.iter().find(|x| x.name == name).map(|x| &x.value)
 }
 
-fn lookup_userid(id: UserId, users: &[LocalUser]) -> Option<LocalUser>{
-    for user in users{
-        if user.id == id{
-            return Some(user.clone())
+//Joins the items, leaving off the last ones with "...and N more" once the text would pass the limit
+fn cut_short(items: Vec<String>, separator: &str, limit: usize) -> String{
+    let mut text = String::new();
+    for (i, item) in items.iter().enumerate(){
+        let more = format!("{}...and {} more", separator, items.len()-i);
+        let last = i+1 == items.len();
+        if text.len() + separator.len() + item.len() + if last {0} else {more.len()} > limit {
+            return if text.is_empty() {more.trim_start_matches(separator).to_string()} else {text + &more};
+        }
+        if !text.is_empty() {
+            text += separator;
         }
+        text += item;
     }
-    None
+    text
 }
 
-async fn localize_user(user: &User, ctx: &Context, guild: GuildId) -> Result<LocalUser>{
-    let member = guild.member(ctx, user.id).await?;
-    Ok(LocalUser{name: member.display_name().to_string(), id:user.id, user: user.clone()})
+//The draft a setup command works on: the one named, else the caller's own, else the guild's only one
+fn pick_draft(setups: &HashMap<String, MatchMatrixSetup>, options: &[ResolvedOption], user: UserId) -> Result<String>{
+    if let Some(ResolvedValue::String(name)) = find_option(options, "draft"){
+        let name = name.to_lowercase();
+        return if setups.contains_key(&name) {Ok(name)} else {Err(anyhow!("no setup in progress called {}", name))};
+    }
+    let own: Vec<&String> = setups.iter().filter(|(_, x)| x.organizer == Some(user)).map(|(name, _)| name).collect();
+    match (own.as_slice(), setups.len()) {
+        ([name], _) => Ok(name.to_string()),
+        (_, 1) => Ok(setups.keys().next().unwrap().clone()),
+        (_, 0) => Err(anyhow!("no setup in progress, start one with /begin")),
+        _ => Err(anyhow!("{} setups are in progress, pick one with the draft option", setups.len())),
+    }
 }
-fn member_to_user(member: &Member) -> LocalUser{
-    LocalUser{name: member.display_name().to_string(), id:member.user.id, user: member.user.clone()}
+
+fn leg_option() -> CreateCommandOption{
+    CreateCommandOption::new(CommandOptionType::String, "leg", "Was this your home or away match (default whichever is unplayed)")
+        .add_string_choice("Home", "home").add_string_choice("Away", "away")
+}
+//Posts to the audit channel if there is one, failures only get printed so they never block a command
+async fn audit(discord: &dyn Discord, channel: Option<ChannelId>, text: String){
+    let Some(channel) = channel else { return };
+    if let Err(why) = discord.send_message(channel, CreateMessage::new().allowed_mentions(CreateAllowedMentions::new()).content(text)).await{
+        println!("Error posting to audit channel {channel}: {why}");
+    }
+}
+
+fn find_leg(options: &[ResolvedOption]) -> Result<Option<Leg>>{
+    match find_option(options, "leg") {
+        Some(ResolvedValue::String(leg)) => Ok(Some(Leg::get(leg).context("leg must be home or away")?)),
+        _ => Ok(None),
+    }
+}
+
+//Reads a UTC date, `2025-06-30` meaning the end of that day, or a date and time like `2025-06-30 18:00`
+fn parse_deadline(text: &str) -> Result<Timestamp>{
+    static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\d{4}-\d{2}-\d{2})(?:[ T](\d{1,2}):(\d{2}))?$").unwrap());
+    let parts = RE.captures(text.trim()).context(format!("{} is not a date like 2025-06-30 or 2025-06-30 18:00", text))?;
+    let time = match (parts.get(2), parts.get(3)) {
+        (Some(hour), Some(minute)) => format!("{:0>2}:{}:00", hour.as_str(), minute.as_str()),
+        _ => "23:59:59".to_string(),
+    };
+    Timestamp::parse(&format!("{}T{}Z", &parts[1], time)).map_err(|_| anyhow!("{} is not a valid date", text))
+}
+//Reads a list of days like `7, 3, 1`, largest first
+fn parse_reminders(text: &str) -> Result<Vec<u32>>{
+    let mut days = text.split(',').map(|x| x.trim().parse::<u32>().ok().filter(|x| *x > 0).context(format!("{} is not a number of days", x.trim())))
+        .collect::<Result<Vec<_>>>()?;
+    days.sort_by(|a, b| b.cmp(a));
+    days.dedup();
+    Ok(days)
+}
+
+//Reads a message link, or a bare message id meaning a message in the given channel
+fn parse_message_link(text: &str, channel: ChannelId) -> Result<(ChannelId, MessageId)>{
+    static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(?:https://(?:\w+\.)?discord(?:app)?\.com/channels/\d+/(\d+)/)?(\d+)$").unwrap());
+    let parts = RE.captures(text.trim()).context(format!("{} is not a message link or id", text))?;
+    //Ids are never 0, and the id types panic on it
+    let id = |x: &str| x.parse::<NonZeroU64>().map_err(|_| anyhow!("{} is not a message link or id", text));
+    let channel = match parts.get(1) {
+        Some(channel) => ChannelId::from(id(channel.as_str())?),
+        None => channel,
+    };
+    Ok((channel, MessageId::from(id(&parts[2])?)))
+}
+
+fn is_moderator(member: Option<&Member>) -> bool{
+    member.and_then(|x| x.permissions).is_some_and(|x| x.moderate_members())
+}
+
+fn lookup_userid(id: UserId, users: &[Player]) -> Option<Player>{
+    users.iter().find(|x| x.id == player_id(id)).cloned()
+}
+
+async fn localize_user(user: &User, discord: &dyn Discord, guild: GuildId) -> Result<Player>{
+    Ok(Player{name: discord.member_name(guild, user.id).await?, id: player_id(user.id)})
 }
 
 impl Handler{
-    fn new() -> Self{
-        Handler {setup_data: SCCHashMap::new(), match_data: SCCHashMap::new()}
+    fn new(storage: Storage) -> Self{
+        Handler {setup_data: Arc::new(SCCHashMap::new()), match_data: Arc::new(SCCHashMap::new()), bracket_data: Arc::new(SCCHashMap::new()),
+            config_data: Arc::new(SCCHashMap::new()), storage: Arc::new(storage), sweeper_started: Arc::new(AtomicBool::new(false))}
+    }
+
+    async fn persist(&self, guild: GuildId) -> Result<()>{
+        let setups = self.setup_data.get_async(&guild).await;
+        let matrices = self.match_data.get_async(&guild).await;
+        let brackets = self.bracket_data.get_async(&guild).await;
+        let config = self.config_data.get_async(&guild).await;
+        self.storage.save(guild, &GuildStateRef{setups: setups.as_ref().map(|x| x.get()), matrices: matrices.as_ref().map(|x| x.get()),
+            brackets: brackets.as_ref().map(|x| x.get()), config: config.as_ref().map(|x| x.get())}).await
+    }
+
+    async fn restore(&self, discord: &dyn Discord) -> Result<()>{
+        let mut restored = Vec::new();
+        for (guild, state) in self.storage.load_all().await?{
+            if !state.setups.is_empty(){
+                let _ = self.setup_data.insert_async(guild, state.setups).await;
+            }
+            let _ = self.config_data.insert_async(guild, state.config).await;
+            if (state.matrices.is_empty() && state.brackets.is_empty()) || self.match_data.contains_async(&guild).await { continue }
+            let _ = self.match_data.insert_async(guild, state.matrices).await;
+            let _ = self.bracket_data.insert_async(guild, state.brackets).await;
+            restored.push(guild);
+        }
+        //Everything is in memory before Discord is called, so a guild that fails here keeps its saved state
+        for guild in restored{
+            let Some(tournaments) = self.match_data.get_async(&guild).await else { continue };
+            if let Err(why) = self.reset_commands(discord, &guild, tournaments.get()).await{
+                println!("Error registering commands for guild {guild}: {why}");
+            }
+        }
+        Ok(())
+    }
+
+    async fn reset_commands(&self, discord: &dyn Discord, guild: &GuildId, tournaments: &HashMap<String, MatchMatrix>) -> Result<()>{
+        let brackets = self.bracket_data.get_async(guild).await;
+        let no_brackets = HashMap::new();
+        Self::reset_tournament_commands(discord, guild, tournaments, brackets.as_ref().map_or(&no_brackets, |x| x.get())).await
+    }
+
+    async fn guild_theme(&self, guild: GuildId) -> Theme{
+        self.config_data.read_async(&guild, |_, x| x.theme.clone()).await.flatten().unwrap_or_default()
     }
 
     async fn begin(&self, command: &CommandInteraction) -> Result<String>{
         let options = &command.data.options();
         
         let guild = command.guild_id.context("guild not found in begin setup")?;
-        if self.setup_data.contains_async(&guild).await{
-            return Err(anyhow!("Starting new setup when already in the middle of setup"));
-        }
 
         let Some(ResolvedOption {
             value: ResolvedValue::String(threadname), ..
@@ -177,92 +536,336 @@ impl Handler{
         static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[-_\p{L}\p{N}\p{sc=Deva}\p{sc=Thai}]{1,32}$").unwrap());
         if !RE.is_match(&shortname) {return Err(anyhow!("invalid command name"))};
 
-        self.setup_data.insert_async(guild, MatchMatrixSetup{threadname, shortname, users:Vec::new()}).await
-            .map_err(|(_k, _v)| anyhow!("Error: begin setup insert failed after check!"))?;
+        let format = match (find_option(options, "format"), find_option(options, "first-to")) {
+            (None, None) => MatchFormat::default(),
+            (Some(ResolvedValue::String(format)), None) => {
+                let games = format.strip_prefix("bo").and_then(|x| x.parse().ok()).context("invalid match format")?;
+                MatchFormat::best_of(games)?
+            },
+            (None, Some(ResolvedValue::Integer(first_to))) => {
+                let first_to = u8::try_from(*first_to).ok().filter(|x| (1..=MatchFormat::MAX_FIRST_TO).contains(x))
+                    .context(format!("first-to must be between 1 and {}", MatchFormat::MAX_FIRST_TO))?;
+                MatchFormat{first_to, draws: false}
+            },
+            (Some(_), Some(_)) => return Err(anyhow!("give either a format or first-to, not both")),
+            _ => return Err(anyhow!("Bad command arguments")),
+        };
+        let format = MatchFormat{draws: matches!(find_option(options, "draws"), Some(ResolvedValue::Boolean(true))), ..format};
+        let tiebreakers = match find_option(options, "tiebreakers") {
+            Some(ResolvedValue::String(list)) => Tiebreaker::parse_list(list)?,
+            None => standings::default_tiebreakers(),
+            _ => return Err(anyhow!("Bad command arguments")),
+        };
+        let swiss_rounds = match (find_option(options, "mode"), find_option(options, "rounds")) {
+            (None | Some(ResolvedValue::String("round-robin")), None) => None,
+            (Some(ResolvedValue::String("swiss")), None) => Some(0), //Decided at /create once the player count is known
+            (Some(ResolvedValue::String("swiss")), Some(ResolvedValue::Integer(rounds))) => Some(u32::try_from(*rounds).ok().filter(|x| *x > 0).context("rounds must be positive")?),
+            (_, Some(_)) => return Err(anyhow!("rounds can only be given for a Swiss tournament")),
+            _ => return Err(anyhow!("Bad command arguments")),
+        };
+        let double = matches!(find_option(options, "double"), Some(ResolvedValue::Boolean(true)));
+        if double && swiss_rounds.is_some() {
+            return Err(anyhow!("Swiss tournaments can't be played home and away"));
+        }
+        let confirm = matches!(find_option(options, "confirm"), Some(ResolvedValue::Boolean(true)));
+        let confirm_timeout = match find_option(options, "confirm-timeout") {
+            Some(ResolvedValue::Integer(minutes)) if confirm => Some(u32::try_from(*minutes).ok().filter(|x| *x > 0).context("confirm-timeout must be positive")?),
+            Some(_) => return Err(anyhow!("confirm-timeout can only be given along with confirm")),
+            None => None,
+        };
+        let policy = match find_option(options, "reporting") {
+            Some(ResolvedValue::String(policy)) => ReportPolicy::get(policy).context("unknown reporting policy")?,
+            _ => ReportPolicy::default(),
+        };
+        let audit_channel = match find_option(options, "audit-channel") {
+            Some(ResolvedValue::Channel(channel)) => Some(channel.id),
+            _ => None,
+        };
+        let theme = match find_option(options, "theme") {
+            Some(ResolvedValue::String(name)) => Some(Theme::preset(name).context("unknown theme")?),
+            _ => None,
+        };
+        let image = matches!(find_option(options, "image"), Some(ResolvedValue::Boolean(true)));
+        let withdrawals = match find_option(options, "withdrawals") {
+            Some(ResolvedValue::String(policy)) => Withdrawal::get(policy).context("unknown withdrawal policy")?,
+            _ => Withdrawal::default(),
+        };
+
+        if self.match_data.read_async(&guild, |_, x| x.contains_key(&shortname)).await == Some(true)
+            || self.bracket_data.read_async(&guild, |_, x| x.contains_key(&shortname)).await == Some(true){
+            return Err(anyhow!("A tournament called {} is already running", shortname));
+        }
+        let mut setups = self.setup_data.entry_async(guild).await.or_default();
+        if setups.get().contains_key(&shortname){
+            return Err(anyhow!("{} is already being set up, /cancel it or pick another cmd", shortname));
+        }
+        setups.get_mut().insert(shortname.clone(), MatchMatrixSetup{threadname, shortname, users:Vec::new(), format, tiebreakers, swiss_rounds, double, confirm, confirm_timeout, policy, audit_channel, theme, image, withdrawals,
+            organizer: Some(command.user.id), signup: None});
         Ok("Success".to_string())
     }
 
-    async fn add_users(&self, ctx: &Context, command: &CommandInteraction) -> Result<String>{
+    async fn add_users(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
         let options = &command.data.options();
 
         let guild = command.guild_id.context("guild not found in add name")?;
-        if !self.setup_data.contains_async(&guild).await{
-            return Err(anyhow!("Add name when not doing setup"));
+        //Users given one by one, then the role's members, then the sign-up message's reactions
+        let given: Vec<&User> = options.iter().filter_map(|x| match x.value {
+            ResolvedValue::User(user, _) => Some(user),
+            _ => None,
+        }).collect();
+        let role = match find_option(options, "role") {
+            Some(ResolvedValue::Role(role)) => Some(role.id),
+            _ => None,
+        };
+        let reactions = match find_option(options, "reactions") {
+            Some(ResolvedValue::String(link)) => Some(parse_message_link(link, command.channel_id)?),
+            _ => None,
+        };
+        if given.is_empty() && role.is_none() && reactions.is_none(){
+            return Err(anyhow!("Give some players, a role or a sign-up message to add"));
         }
-        let mut setup_holder = self.setup_data.get_async(&guild).await.context("Adding user without match setup")?;
-        let setup = setup_holder.get_mut();
-        let mut users_added = 0;
+        //Names are looked up before the setup is locked, skipping anyone who has left the server
+        let mut candidates = Vec::new();
         let mut extra_info = String::new();
-        for current_user in 0..10{
-            let Some(ResolvedOption {
-                value: ResolvedValue::User(user, _), ..
-            }) = options.get(current_user) else {continue;};
-            let localized = localize_user(user, ctx, guild).await?;
-            if lookup_userid(user.id, &setup.users).is_some(){
-                extra_info += &localized.name;
+        for user in given.into_iter().filter(|x| !x.bot){
+            match localize_user(user, discord, guild).await {
+                Ok(player) => candidates.push(player),
+                Err(_) => extra_info += &format!("{} isn't in the server.\n", user.name),
+            }
+        }
+        if let Some(role) = role{
+            //Members come with their display names
+            candidates.extend(discord.role_members(guild, role).await?.into_iter().filter(|x| !x.user.bot)
+                .map(|x| Player{name: x.display_name().to_string(), id: player_id(x.user.id)}));
+        }
+        if let Some((channel, message)) = reactions{
+            for user in discord.reaction_users(channel, message).await?.iter().filter(|x| !x.bot){
+                match localize_user(user, discord, guild).await {
+                    Ok(player) => candidates.push(player),
+                    Err(_) => extra_info += &format!("{} isn't in the server.\n", user.name),
+                }
+            }
+        }
+
+        let mut setup_holder = self.setup_data.get_async(&guild).await.context("Add name when not doing setup")?;
+        let draft = pick_draft(setup_holder.get(), options, command.user.id)?;
+        let setup = setup_holder.get_mut().get_mut(&draft).context("Adding user without match setup")?;
+        let mut users_added = 0;
+        for player in candidates{
+            if setup.users.iter().any(|x| x.id == player.id){
+                extra_info += &player.name;
                 extra_info += " already included.\n";
                 continue;
             }
-            setup.users.push(localized);
+            setup.users.push(player);
             users_added += 1;
         }
+        if let Some(signup) = &mut setup.signup{
+            signup.waitlist.retain(|x| lookup_userid(user_id(x.id), &setup.users).is_none());
+            setup.refresh_signup(discord).await;
+        }
 
         Ok(format!("{}Added {} new players. Full list of {}: {:?}", extra_info, users_added, setup.users.len(), setup.users.iter().map(|x|&x.name).collect::<Vec<_>>()))
     }
 
-    async fn cancel(&self, command: &CommandInteraction) -> Result<String>{
+    async fn cancel(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
         let guild = command.guild_id.context("guild not found in cancel")?;
-        if !self.setup_data.contains_async(&guild).await{
-            return Err(anyhow!("Cancel setup when not doing setup"));
+        let mut setups = self.setup_data.get_async(&guild).await.context("Cancel setup when not doing setup")?;
+        let draft = pick_draft(setups.get(), &command.data.options(), command.user.id)?;
+        if let Some(mut setup) = setups.get_mut().remove(&draft){
+            if let Some(signup) = &mut setup.signup{
+                signup.closed = true;
+                if let Err(why) = discord.edit_message(signup.channel, signup.message, EditMessage::new()
+                    .content(format!("Sign-up for {} was cancelled", setup.threadname)).components(Vec::new())).await{
+                    println!("Error closing the sign-up for {}: {why}", draft);
+                }
+            }
         }
-        self.setup_data.remove_async(&guild).await;
-        Ok("Success".to_string())
+        if setups.get().is_empty(){
+            let _ = setups.remove();
+        }
+        Ok(format!("Cancelled the setup of {}", draft))
     }
 
-    async fn create(&self, ctx: &Context, command: &CommandInteraction) -> Result<String>{
-        let guild = command.guild_id.context("guild not found in create")?;
-        let (_, setup) = self.setup_data.remove_async(&guild).await.context("Create called but setup data not found!")?;
-        let thread_builder = CreateThread::new(&setup.threadname)
-            .kind(ChannelType::PublicThread);
-        let thread = command.channel_id.create_thread(&ctx.http, thread_builder).await?;
+    //Posts a message players can join the draft from, replacing any earlier one
+    async fn signup(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
+        let options = &command.data.options();
+        let guild = command.guild_id.context("guild not found in signup")?;
+        let cap = match find_option(options, "cap") {
+            Some(ResolvedValue::Integer(cap)) => Some(u32::try_from(*cap).ok().filter(|x| *x >= 2).context("cap must be at least 2")?),
+            _ => None,
+        };
+        let closes = match find_option(options, "closes") {
+            Some(ResolvedValue::String(date)) => Some(parse_deadline(date)?),
+            _ => None,
+        };
+        if closes.is_some_and(|x| x.unix_timestamp() <= Timestamp::now().unix_timestamp()) {
+            return Err(anyhow!("the sign-up has to close in the future"));
+        }
+        let mut setups = self.setup_data.get_async(&guild).await.context("Sign-up when not doing setup")?;
+        let draft = pick_draft(setups.get(), options, command.user.id)?;
+        let setup = setups.get_mut().get_mut(&draft).context("Sign-up without match setup")?;
+        let mut waitlist = Vec::new();
+        if let Some(old) = setup.signup.take(){
+            waitlist = old.waitlist;
+            if let Err(why) = discord.edit_message(old.channel, old.message, EditMessage::new().content("This sign-up has moved").components(Vec::new())).await{
+                println!("Error closing the old sign-up for {}: {why}", draft);
+            }
+        }
+        //Posted first so the roster can be drawn in it with its buttons
+        let message = discord.say(command.channel_id, format!("Opening sign-up for {}", setup.threadname)).await?;
+        setup.signup = Some(Signup{channel: command.channel_id, message, cap, closes, waitlist: Vec::new(), closed: false});
+        //A raised cap lets the waitlist in straight away
+        let mut waitlist = waitlist.into_iter();
+        while !setup.is_full() {
+            let Some(player) = waitlist.next() else { break };
+            setup.users.push(player);
+        }
+        setup.signup.as_mut().context("sign-up not found")?.waitlist = waitlist.collect();
+        setup.refresh_signup(discord).await;
+        Ok(format!("Sign-up for {} posted", setup.threadname))
+    }
 
-        let mut initial_message_str = String::new();
-        for user in &setup.users{
-            initial_message_str += &format!("<@{}> ", user.id);
+    async fn signup_button(&self, discord: &dyn Discord, component: &ComponentInteraction, action: &str) -> Result<String>{
+        let guild = component.guild_id.context("guild not found for sign-up")?;
+        let (action, draft) = action.split_once(':').context("bad sign-up button")?;
+        let mut setups = self.setup_data.get_async(&guild).await.context("this sign-up has ended")?;
+        let setup = setups.get_mut().get_mut(draft).context("this sign-up has ended")?;
+        let signup = setup.signup.as_ref().context("this sign-up has ended")?;
+        if signup.closed || signup.closes.is_some_and(|x| x.unix_timestamp() <= Timestamp::now().unix_timestamp()) {
+            return Err(anyhow!("Sign-up for {} has closed", setup.threadname));
+        }
+        let waiting = signup.waitlist.iter().position(|x| x.id == player_id(component.user.id));
+        let joined = setup.users.iter().position(|x| x.id == player_id(component.user.id));
+        let reply = match (action, joined, waiting) {
+            ("join", Some(_), _) => return Err(anyhow!("You're already signed up for {}", setup.threadname)),
+            ("join", None, Some(place)) => return Err(anyhow!("You're already number {} on the waitlist for {}", place+1, setup.threadname)),
+            ("join", None, None) => {
+                let player = localize_user(&component.user, discord, guild).await?;
+                if setup.is_full() {
+                    let signup = setup.signup.as_mut().context("sign-up not found")?;
+                    signup.waitlist.push(player);
+                    format!("{} is full, you're number {} on the waitlist", setup.threadname, signup.waitlist.len())
+                } else {
+                    setup.users.push(player);
+                    format!("You're signed up for {}", setup.threadname)
+                }
+            },
+            ("leave", Some(index), _) => {
+                setup.users.remove(index);
+                let signup = setup.signup.as_mut().context("sign-up not found")?;
+                //The first player waiting takes the free place
+                if signup.cap.is_some_and(|cap| setup.users.len() < cap as usize) && !signup.waitlist.is_empty() {
+                    let promoted = signup.waitlist.remove(0);
+                    let (channel, id) = (signup.channel, promoted.id);
+                    setup.users.push(promoted);
+                    //The place is theirs either way, so a failed ping only gets printed
+                    if let Err(why) = discord.send_message(channel, CreateMessage::new()
+                        .allowed_mentions(CreateAllowedMentions::new().users([user_id(id)]))
+                        .content(format!("<@{}> moved up from the waitlist into {}", id, setup.threadname))).await{
+                        println!("Error announcing a waitlist promotion for {}: {why}", setup.shortname);
+                    }
+                }
+                format!("You left {}", setup.threadname)
+            },
+            ("leave", None, Some(place)) => {
+                setup.signup.as_mut().context("sign-up not found")?.waitlist.remove(place);
+                format!("You left the waitlist for {}", setup.threadname)
+            },
+            ("leave", None, None) => return Err(anyhow!("You aren't signed up for {}", setup.threadname)),
+            _ => return Err(anyhow!("Unknown button")),
+        };
+        setup.refresh_signup(discord).await;
+        Ok(reply)
+    }
+
+    //Takes the buttons off sign-ups whose close time has passed
+    async fn close_signups(&self, discord: &dyn Discord) -> Result<()>{
+        let mut guilds = Vec::new();
+        self.setup_data.scan_async(|guild, _| guilds.push(*guild)).await;
+        for guild in guilds{
+            let mut changed = false;
+            if let Some(mut setups) = self.setup_data.get_async(&guild).await{
+                for setup in setups.get_mut().values_mut(){
+                    let Some(signup) = &mut setup.signup else { continue };
+                    if signup.closed || signup.closes.is_none_or(|x| x.unix_timestamp() > Timestamp::now().unix_timestamp()) { continue }
+                    signup.closed = true;
+                    setup.refresh_signup(discord).await;
+                    changed = true;
+                }
+            }
+            if changed {
+                if let Err(why) = self.persist(guild).await{
+                    println!("Error saving guild {guild}: {why}");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn create(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
+        let guild = command.guild_id.context("guild not found in create")?;
+        let mut setups = self.setup_data.get_async(&guild).await.context("Create called but setup data not found!")?;
+        let draft = pick_draft(setups.get(), &command.data.options(), command.user.id)?;
+        let mut setup = setups.get_mut().remove(&draft).context("Create called but setup data not found!")?;
+        if setups.get().is_empty(){
+            let _ = setups.remove();
+        }
+        else{
+            drop(setups);
+        }
+        if let Some(signup) = &mut setup.signup{
+            signup.closed = true;
+            setup.refresh_signup(discord).await;
+        }
+        if setup.swiss_rounds == Some(0) {
+            setup.swiss_rounds = Some(SwissState::default_rounds(setup.users.len()));
         }
-        thread.send_message(&ctx.http, CreateMessage::new()
-            .allowed_mentions(CreateAllowedMentions::new().users(setup.users.iter().map(|x| &x.user)))
+        let thread = discord.create_thread(command.channel_id, &setup.threadname).await?;
+
+        let theme = match setup.theme {
+            Some(theme) => theme,
+            None => self.guild_theme(guild).await,
+        };
+        //The intro records the theme so /reprocess can read the grid back in it
+        let intro = Intro{players: setup.users.iter().map(|x| x.id).collect(), shortname: setup.shortname.clone(), format: setup.format,
+            double: setup.double, confirm: setup.confirm, swiss_rounds: setup.swiss_rounds, theme: Some(theme.clone())};
+        discord.send_message(thread, CreateMessage::new()
+            .allowed_mentions(CreateAllowedMentions::new().users(setup.users.iter().map(|x| user_id(x.id))))
             .flags(MessageFlags::SUPPRESS_NOTIFICATIONS)
-            .content(initial_message_str+" Report your results here using the command /"+&setup.shortname+" or /result"))
+            .content(intro.render()))
             .await?;
 
-        let mut results = HashMap::new();
-        for y in &setup.users{
-            for x in &setup.users{
-                let result = if x == y {MatchResult::Unplayable} else {MatchResult::NotPlayed};
-                results.insert((x.id, y.id), result);
-            }
+        let core = Matrix::new(setup.users, setup.format, setup.tiebreakers, setup.swiss_rounds, setup.double);
+        let msg_count = core.message_count();
+        let mut matrix = MatchMatrix{thread, threadname:setup.threadname, mainposts: Vec::new(), core, history: Vec::new(),
+            confirm: setup.confirm, confirm_timeout: setup.confirm_timeout, pending: Vec::new(), disputes: Vec::new(), organizer: Some(command.user.id),
+            policy: setup.policy, audit_channel: setup.audit_channel, theme, legend_post: None, image_post: None, withdrawals: setup.withdrawals,
+            deadline: None, reminders: Vec::new(), reminders_sent: Vec::new()};
+        if matrix.core.swiss.is_some(){
+            Self::pair_next_round(&mut matrix)?;
         }
-        let approx_char_count = ((setup.users.len()+1)*(setup.users.len()+1))*25; // Up to 25 characters per matrix square, plus the username and bell, plus the row of letters
-        let msg_count = (approx_char_count/1800)+1; //2000 character limit, plus some wiggle room to be safe
-        let mut mainposts = Vec::new();
-        let messages = render_grid(&setup.users, &results, &HashSet::new(), &setup.threadname, msg_count)?;
+        let messages = render_grid(&matrix.core, &matrix.header(), &matrix.theme, &matrix.marks(), true, msg_count)?;
         for msg in messages{
-            mainposts.push(thread.say(&ctx.http, msg).await?.id);
+            matrix.mainposts.push(discord.say(thread, msg).await?);
         }
 
-        thread.say(&ctx.http, ":cloud: match available\n:full_moon: match won 2-0\n:waning_gibbous_moon: match won 2-1\n\
-            :waxing_crescent_moon: match lost 1-2\n:new_moon: match lost 0-2\n:black_small_square: cannot play yourself").await?;
+        matrix.legend_post = Some(discord.say(thread, matrix.legend()).await?);
+        if setup.image {
+            matrix.image_post = Some(discord.send_file(thread, GRID_IMAGE, matrix.render_png()).await?);
+        }
+        if matrix.core.swiss.is_some(){
+            Self::announce_pairings(discord, &matrix).await?;
+        }
 
         let mut match_vec = self.match_data.entry_async(guild).await.or_insert(HashMap::new());
-        let matrix = MatchMatrix{thread: thread.id, threadname:setup.threadname, mainposts, users: setup.users, results, disabled_fam: HashSet::new()};
         match_vec.get_mut().insert(setup.shortname, matrix);
-        Self::reset_tournament_commands(ctx, &guild, &match_vec).await?;
+        self.reset_commands(discord, &guild, &match_vec).await?;
 
         Ok("Success!".to_string())
     }
 
-    async fn report_result_command(&self, ctx: &Context, command: &CommandInteraction) -> Result<String>{
+    async fn report_result_command(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
         let options = &command.data.options();
         let guild = command.guild_id.context("guild not found for result report")?;
         let Some(mut match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
@@ -277,14 +880,21 @@ impl Handler{
         let commandshortname = &command.data.name;
         for (shortname, matrix) in match_data_list.iter_mut(){
             if commandshortname == shortname{
-                let opponent = lookup_userid(opponent.parse()?, &matrix.users).context("User not found")?;
-                let player = lookup_userid(command.user.id, &matrix.users).context("User not found")?;
-                return self.report_result_generic(ctx, matrix, &player, result_str, &opponent, &command.user).await;
+                let opponent = lookup_userid(opponent.parse().context("pick an opponent from the list")?, &matrix.core.users).context("User not found")?;
+                let player = lookup_userid(command.user.id, &matrix.core.users).context("User not found")?;
+                return self.report_result_generic(discord, matrix, &player, result_str, &opponent, command).await;
+            }
+        }
+        if let Some(mut bracket_list) = self.bracket_data.get_async(&guild).await{
+            if let Some(bracket) = bracket_list.get_mut().get_mut(commandshortname){
+                let opponent = lookup_userid(opponent.parse().context("pick an opponent from the list")?, &bracket.bracket.users).context("User not found")?;
+                let player = lookup_userid(command.user.id, &bracket.bracket.users).context("User not found")?;
+                return Self::report_bracket_result(discord, bracket, &player, result_str, &opponent, command).await;
             }
         }
         Err(anyhow!("Illegal command/name not found to report to"))
     }
-    async fn report_result_any(&self, ctx: &Context, command: &CommandInteraction) -> Result<String>{
+    async fn report_result_any(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
         let options = &command.data.options();
         let guild = command.guild_id.context("guild not found for result report")?;
         let Some(mut match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
@@ -302,37 +912,456 @@ impl Handler{
         };
         for (_, matrix) in match_data_list.iter_mut(){
             if command.channel_id == matrix.thread{
-                let player = lookup_userid(player.id, &matrix.users).context("User not found")?;
-                let opponent = lookup_userid(opponent.id, &matrix.users).context("User not found")?;
-                return self.report_result_generic(ctx, matrix, &player, result_str, &opponent, &command.user).await;
+                let player = lookup_userid(player.id, &matrix.core.users).context("User not found")?;
+                let opponent = lookup_userid(opponent.id, &matrix.core.users).context("User not found")?;
+                return self.report_result_generic(discord, matrix, &player, result_str, &opponent, command).await;
+            }
+        }
+        if let Some(mut bracket_list) = self.bracket_data.get_async(&guild).await{
+            if let Some(bracket) = bracket_list.get_mut().values_mut().find(|x| x.thread == command.channel_id){
+                let player = lookup_userid(player.id, &bracket.bracket.users).context("User not found")?;
+                let opponent = lookup_userid(opponent.id, &bracket.bracket.users).context("User not found")?;
+                return Self::report_bracket_result(discord, bracket, &player, result_str, &opponent, command).await;
             }
         }
         Err(anyhow!("Attempted to report but results thread not found"))
     }
-    async fn report_result_generic(&self, ctx: &Context, matrix: &mut MatchMatrix, player: &LocalUser, result_str: &str, opponent: &LocalUser, reporter_user: &User) -> Result<String>{
+    async fn report_result_generic(&self, discord: &dyn Discord, matrix: &mut MatchMatrix, player: &Player, result_str: &str, opponent: &Player, command: &CommandInteraction) -> Result<String>{
+        let reporter_user = &command.user;
+        let leg = find_leg(&command.data.options())?;
         if player.id == opponent.id {
             return Err(anyhow!("trying to report a match played against the same player"));
         }
-        let old_result = matrix.results.get(&(opponent.id, player.id)).context("match result not available - bad user id?")?.clone();
-        let result = MatchResult::get(result_str);
-        let x = &mut matrix.results.get_mut(&(player.id, opponent.id)).context("match not found - bad user id?")?;
-        **x = result.invert();
-        let x2 = &mut matrix.results.get_mut(&(opponent.id, player.id)).context("reverse match not found - wtf?")?;
-        **x2 = result;
-        if old_result == MatchResult::NotPlayed {
-            matrix.thread.say(&ctx.http, format!("{} reports {} {} {}", reporter_user, player.name, result_str, opponent.name)).await?;
+        if !matrix.policy.allows(reporter_user.id, matrix.is_organizer(reporter_user.id, command.member.as_deref()), player.id, opponent.id) {
+            audit(discord, matrix.audit_channel, format!("Rejected report in {} from {}: {} {} {}", matrix.threadname, reporter_user, player.name, result_str, opponent.name)).await;
+            return Err(anyhow!("You can't report this match, in {} {}", matrix.threadname, matrix.policy.description()));
+        }
+        let format = matrix.core.format;
+        let result = MatchResult::get(result_str).filter(|x| format.allows(x));
+        let Some(result) = result else {
+            return Err(anyhow!("{} is not a valid score for a {} match", result_str, format.description()));
+        };
+        let leg = matrix.core.pick_leg(player.id, opponent.id, leg)?;
+        //Away matches are stored as the opponent's home match
+        let (player_id, opponent_id, _) = matrix.core.match_key(player.id, opponent.id, leg)?;
+        let old_result = matrix.core.result(player_id, opponent_id).context("match result not available - bad user id?")?.clone();
+        let result = if leg == Some(Leg::Away) {result.invert()} else {result};
+        if matrix.core.swiss.as_ref().is_some_and(|x| !x.has_played(player.id, opponent.id)) {
+            return Err(anyhow!("{} and {} have not been paired against each other", player.name, opponent.name));
+        }
+        let leg_str = match leg {
+            Some(Leg::Home) => " (home)",
+            Some(Leg::Away) => " (away)",
+            None => "",
+        };
+        let shown_old_result = if leg == Some(Leg::Away) {old_result.invert()} else {old_result.clone()};
+        let announcement = if shown_old_result == MatchResult::NotPlayed {
+            format!("{} reports {} {} {}{}", reporter_user, player.name, result_str, opponent.name, leg_str)
         } else {
-            matrix.thread.say(&ctx.http, format!("{} reports {} {} {}, overwriting previous result of {}", reporter_user, player.name, result_str, opponent.name, old_result.to_str())).await?;
+            format!("{} reports {} {} {}{}, overwriting previous result of {}", reporter_user, player.name, result_str, opponent.name, leg_str, shown_old_result.to_str())
+        };
+        if matrix.is_disputed(player_id, opponent_id) {
+            return Err(anyhow!("the match between {} and {} is disputed, an organizer can settle it with /resolve", player.name, opponent.name));
+        }
+        //Moderators can skip the confirmation step
+        if matrix.confirm && !is_moderator(command.member.as_deref()) {
+            return Self::request_confirmation(discord, matrix, PendingReport{reporter: reporter_user.id, player: player_id, opponent: opponent_id,
+                result, announcement, timestamp: Timestamp::now(), interaction: command.id, message: MessageId::default()}).await;
+        }
+        Self::commit_report(discord, matrix, ReportEvent{reporter: reporter_user.id, player: player_id, opponent: opponent_id,
+            old_result, new_result: result, timestamp: Timestamp::now(), interaction: command.id, undo_of: None, note: None}, announcement).await?;
+        Ok("Success".to_string())
+    }
+    //Records a report in the results and history, announcing it with an Undo button
+    async fn commit_report(discord: &dyn Discord, matrix: &mut MatchMatrix, event: ReportEvent, announcement: String) -> Result<()>{
+        let round_was_complete = matrix.core.swiss.as_ref().is_none_or(|x| swiss::round_complete(&matrix.core, x));
+        matrix.core.set_result(event.player, event.opponent, event.new_result.clone())?;
+        //A direct report replaces any report still waiting on confirmation, a confirmed one leaves pending here
+        let superseded = matrix.take_pending(event.player, event.opponent);
+        for report in superseded.iter().filter(|x| x.interaction != event.interaction){
+            Self::close_pending(discord, matrix, report).await?;
         }
+        matrix.history.push(event);
+        let undo_button = CreateButton::new(format!("undo:{}", matrix.history.len()-1)).label("Undo").style(ButtonStyle::Secondary);
+        discord.send_message(matrix.thread, CreateMessage::new().content(announcement)
+            .components(vec![CreateActionRow::Buttons(vec![undo_button])])).await?;
 
-        let messages = render_grid(&matrix.users, &matrix.results, &matrix.disabled_fam, &matrix.threadname, matrix.mainposts.len())?;
-        for (msg, post) in messages.iter().zip(&matrix.mainposts){
-            matrix.thread.message(&ctx.http, post).await?.edit(&ctx.http, EditMessage::new().content(msg)).await?;
+        //Move on once this report finishes the Swiss round
+        if !round_was_complete && matrix.core.swiss.as_ref().is_some_and(|x| swiss::round_complete(&matrix.core, x)) {
+            Self::finish_round(discord, matrix).await?;
+        }
+        Self::update_grid(discord, matrix).await
+    }
+
+    async fn request_confirmation(discord: &dyn Discord, matrix: &mut MatchMatrix, mut report: PendingReport) -> Result<String>{
+        let superseded = matrix.take_pending(report.player, report.opponent);
+        for old_report in superseded{
+            Self::close_pending(discord, matrix, &old_report).await?;
+        }
+        let confirmers: Vec<UserId> = [report.player, report.opponent].into_iter().map(user_id).filter(|x| *x != report.reporter).collect();
+        let mentions: Vec<String> = confirmers.iter().map(|x| format!("<@{}>", x)).collect();
+        let buttons = vec![
+            CreateButton::new(format!("confirm:{}", report.interaction)).label("Confirm").style(ButtonStyle::Success),
+            CreateButton::new(format!("reject:{}", report.interaction)).label("Dispute").style(ButtonStyle::Danger),
+        ];
+        report.message = discord.send_message(matrix.thread, CreateMessage::new()
+            .allowed_mentions(CreateAllowedMentions::new().users(confirmers))
+            .content(format!("{}, waiting for confirmation from {}", report.announcement, mentions.join(" or ")))
+            .components(vec![CreateActionRow::Buttons(buttons)])).await?;
+        matrix.pending.push(report);
+        Self::update_grid(discord, matrix).await?;
+        Ok("Success - the result will be recorded once your opponent confirms it".to_string())
+    }
+    //Removes the Confirm and Dispute buttons from a pending report's message
+    async fn close_pending(discord: &dyn Discord, matrix: &MatchMatrix, report: &PendingReport) -> Result<()>{
+        discord.edit_message(matrix.thread, report.message, EditMessage::new().components(Vec::new())).await?;
+        Ok(())
+    }
+    //The report's buttons must already be gone
+    async fn record_pending(discord: &dyn Discord, matrix: &mut MatchMatrix, index: usize, note: String) -> Result<()>{
+        let report = matrix.pending[index].clone();
+        let result = match matrix.core.result(report.player, report.opponent).cloned() {
+            Some(old_result) => Self::commit_report(discord, matrix, ReportEvent{reporter: report.reporter, player: report.player, opponent: report.opponent,
+                old_result, new_result: report.result.clone(), timestamp: Timestamp::now(), interaction: report.interaction, undo_of: None, note: None},
+                format!("{} ({})", report.announcement, note)).await,
+            None => Err(anyhow!("match not found - bad user id?")),
+        };
+        //Still pending means the result was rejected, which won't change, so it is dropped
+        let rejected = matrix.pending.iter().any(|x| x.interaction == report.interaction);
+        if let (Err(why), true) = (&result, rejected) {
+            matrix.pending.retain(|x| x.interaction != report.interaction);
+            if let Err(why) = discord.say(matrix.thread, format!("{} could not be recorded and was dropped: {}", report.announcement, why)).await{
+                println!("Error announcing a dropped report: {why}");
+            }
+            if let Err(why) = Self::update_grid(discord, matrix).await{
+                println!("Error updating the grid: {why}");
+            }
+        }
+        result
+    }
+    async fn confirm_button(&self, discord: &dyn Discord, component: &ComponentInteraction, interaction: &str, confirm: bool) -> Result<String>{
+        let guild = component.guild_id.context("guild not found for confirmation")?;
+        let Some(mut match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
+        let interaction: InteractionId = interaction.parse::<u64>()?.into();
+        let matrix = match_data_list.get_mut().values_mut().find(|x| x.thread == component.channel_id).context("results thread not found")?;
+        let index = matrix.pending.iter().position(|x| x.interaction == interaction).context("that report is no longer waiting for confirmation")?;
+        let report = &matrix.pending[index];
+        let is_player = component.user.id != report.reporter && (player_id(component.user.id) == report.player || player_id(component.user.id) == report.opponent);
+        if !is_player && !is_moderator(component.member.as_ref()) {
+            return Err(anyhow!("only the other player or a moderator can confirm or dispute this report"));
+        }
+        //Kept pending until its buttons are gone, so a failed edit leaves it to confirm again
+        Self::close_pending(discord, matrix, &matrix.pending[index]).await?;
+        if confirm {
+            Self::record_pending(discord, matrix, index, format!("confirmed by {}", component.user)).await?;
+        } else {
+            let report = matrix.pending.remove(index);
+            let reason = format!("disputed report ({})", report.announcement);
+            Self::raise_dispute(discord, matrix, report.player, report.opponent, &component.user, reason).await?;
         }
         Ok("Success".to_string())
     }
+    async fn send_reminders(&self, discord: &dyn Discord) -> Result<()>{
+        let mut guilds = Vec::new();
+        self.match_data.scan_async(|guild, _| guilds.push(*guild)).await;
+        for guild in guilds{
+            let mut changed = false;
+            if let Some(mut match_data_list) = self.match_data.get_async(&guild).await{
+                for (shortname, matrix) in match_data_list.get_mut().iter_mut(){
+                    let Some(days) = matrix.due_reminder(Timestamp::now().unix_timestamp()) else { continue };
+                    let Some(deadline) = matrix.deadline.map(|x| x.unix_timestamp()) else { continue };
+                    let mut mentions = Vec::new();
+                    let mut players = Vec::new();
+                    for player in &matrix.core.users{
+                        let unplayed = matrix.core.unplayed_opponents(player.id).len();
+                        if unplayed == 0 { continue }
+                        //Players who turned off Find A Match pings are named instead
+                        if matrix.core.disabled_fam.contains(&player.id) {
+                            players.push(format!("{} ({})", player.name, unplayed));
+                        } else {
+                            players.push(format!("<@{}> ({})", player.id, unplayed));
+                            mentions.push(user_id(player.id));
+                        }
+                    }
+                    //Only marked sent once posted, so a failed post is tried again next sweep
+                    if !players.is_empty() {
+                        if let Err(why) = discord.send_message(matrix.thread, CreateMessage::new()
+                            .allowed_mentions(CreateAllowedMentions::new().users(mentions))
+                            .content(format!("Reminder: {} ends <t:{}:R>. Matches still to play: {}", matrix.threadname, deadline, players.join(", ")))).await{
+                            println!("Error sending a reminder for {shortname}: {why}");
+                            continue;
+                        }
+                    }
+                    //Later reminders cover the earlier ones, so a late sweep doesn't post them all at once
+                    let covered: Vec<u32> = matrix.reminders.iter().copied().filter(|x| *x >= days && !matrix.reminders_sent.contains(x)).collect();
+                    matrix.reminders_sent.extend(covered);
+                    changed = true;
+                }
+            }
+            if changed {
+                if let Err(why) = self.persist(guild).await{
+                    println!("Error saving guild {guild}: {why}");
+                }
+            }
+        }
+        Ok(())
+    }
+    //Records every pending report that has waited longer than its tournament's timeout
+    async fn expire_pending(&self, discord: &dyn Discord) -> Result<()>{
+        let mut guilds = Vec::new();
+        self.match_data.scan_async(|guild, _| guilds.push(*guild)).await;
+        for guild in guilds{
+            let mut changed = false;
+            if let Some(mut match_data_list) = self.match_data.get_async(&guild).await{
+                for (shortname, matrix) in match_data_list.get_mut().iter_mut(){
+                    let Some(timeout) = matrix.confirm_timeout else { continue };
+                    let now = Timestamp::now().unix_timestamp();
+                    let expired: Vec<InteractionId> = matrix.pending.iter().filter(|x| now - x.timestamp.unix_timestamp() >= timeout as i64 * 60)
+                        .map(|x| x.interaction).collect();
+                    for interaction in expired{
+                        //Recording a report can close others for the same match
+                        let Some(index) = matrix.pending.iter().position(|x| x.interaction == interaction) else { continue };
+                        //One that can't be edited is tried again next sweep
+                        if let Err(why) = Self::close_pending(discord, matrix, &matrix.pending[index]).await{
+                            println!("Error timing out a pending report in {shortname}: {why}");
+                            continue;
+                        }
+                        changed = true;
+                        if let Err(why) = Self::record_pending(discord, matrix, index, format!("confirmed automatically after {} minutes", timeout)).await{
+                            println!("Error recording a timed out report in {shortname}: {why}");
+                        }
+                    }
+                }
+            }
+            if changed {
+                if let Err(why) = self.persist(guild).await{
+                    println!("Error saving guild {guild}: {why}");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn pair_next_round(matrix: &mut MatchMatrix) -> Result<()>{
+        let swiss = matrix.core.swiss.as_ref().context("not a Swiss tournament")?;
+        let pairings = swiss::pair_next_round(&matrix.core, swiss)?;
+        matrix.core.swiss.as_mut().context("not a Swiss tournament")?.pairings.push(pairings);
+        Ok(())
+    }
+    async fn announce_pairings(discord: &dyn Discord, matrix: &MatchMatrix) -> Result<()>{
+        let swiss = matrix.core.swiss.as_ref().context("not a Swiss tournament")?;
+        let mentions = swiss.current_round().iter().flat_map(|(x, y)| [Some(*x), *y]).flatten().map(user_id);
+        discord.send_message(matrix.thread, CreateMessage::new()
+            .allowed_mentions(CreateAllowedMentions::new().users(mentions))
+            .content(swiss::render_pairings(&matrix.core, swiss)))
+            .await?;
+        Ok(())
+    }
+    //Pairs the next Swiss round, or announces the end of the last round
+    async fn finish_round(discord: &dyn Discord, matrix: &mut MatchMatrix) -> Result<()>{
+        if matrix.core.swiss.as_ref().context("not a Swiss tournament")?.is_finished() {
+            discord.say(matrix.thread, "All rounds are complete! Organizers can post the final standings with /end".to_string()).await?;
+            return Ok(());
+        }
+        Self::pair_next_round(matrix)?;
+        Self::announce_pairings(discord, matrix).await
+    }
+
+    async fn next_round(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
+        let options = &command.data.options();
+        let guild = command.guild_id.context("guild not found for next round")?;
+        let Some(mut match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
+        let match_data_list = match_data_list.get_mut();
+
+        let Some(ResolvedOption {
+            value: ResolvedValue::String(commandshortname), ..
+        }) = options.get(0) else {return Err(anyhow!("name not found in next round"));};
+
+        let matrix = match_data_list.get_mut(*commandshortname).context("unable to find given name in match list")?;
+        let swiss = matrix.core.swiss.as_ref().context("not a Swiss tournament")?;
+        if swiss.is_finished() {
+            return Err(anyhow!("all {} rounds have already been played", swiss.rounds));
+        }
+        let unreported = swiss.current_round().iter().filter(|(x, y)| y.is_some_and(|y| matrix.core.result(*x, y).is_none_or(|x| !x.is_played()))).count();
+        Self::pair_next_round(matrix)?;
+        Self::announce_pairings(discord, matrix).await?;
+        Self::update_grid(discord, matrix).await?;
+        Ok(format!("Success - started the next round with {} matches unreported", unreported))
+    }
+
+    async fn undo_command(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
+        let guild = command.guild_id.context("guild not found for undo")?;
+        let is_moderator = is_moderator(command.member.as_deref());
+        if let Some(mut match_data_list) = self.match_data.get_async(&guild).await{
+            for (_, matrix) in match_data_list.get_mut().iter_mut(){
+                if command.channel_id == matrix.thread{
+                    //Moderators undo the latest report, everyone else their own latest report
+                    let event_index = (0..matrix.history.len()).rev()
+                        .find(|x| matrix.history[*x].undo_of.is_none() && !matrix.is_undone(*x) && (is_moderator || matrix.history[*x].reporter == command.user.id))
+                        .context("no report found that you can undo")?;
+                    return Self::undo_generic(discord, matrix, event_index, &command.user, is_moderator, command.id).await;
+                }
+            }
+        }
+        if let Some(mut bracket_list) = self.bracket_data.get_async(&guild).await{
+            if let Some(bracket) = bracket_list.get_mut().values_mut().find(|x| x.thread == command.channel_id){
+                return Self::undo_bracket(discord, bracket, &command.user, is_moderator).await;
+            }
+        }
+        Err(anyhow!("Attempted to undo but results thread not found"))
+    }
+    async fn undo_button(&self, discord: &dyn Discord, component: &ComponentInteraction, event_index: &str) -> Result<String>{
+        let guild = component.guild_id.context("guild not found for undo")?;
+        let Some(mut match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
+        let match_data_list = match_data_list.get_mut();
+        let event_index = event_index.parse()?;
+        for (_, matrix) in match_data_list.iter_mut(){
+            if component.channel_id == matrix.thread{
+                return Self::undo_generic(discord, matrix, event_index, &component.user, is_moderator(component.member.as_ref()), component.id).await;
+            }
+        }
+        Err(anyhow!("Attempted to undo but results thread not found"))
+    }
+    async fn undo_generic(discord: &dyn Discord, matrix: &mut MatchMatrix, event_index: usize, user: &User, is_moderator: bool, interaction: InteractionId) -> Result<String>{
+        let event = matrix.history.get(event_index).context("report not found")?.clone();
+        if event.undo_of.is_some() || matrix.is_undone(event_index) {
+            return Err(anyhow!("that report has already been undone"));
+        }
+        if !is_moderator && event.reporter != user.id {
+            return Err(anyhow!("only the original reporter or a moderator can undo a report"));
+        }
+        if !is_moderator && matrix.is_disputed(event.player, event.opponent) {
+            return Err(anyhow!("that match is disputed, an organizer can settle it with /resolve"));
+        }
+        let current = matrix.core.result(event.player, event.opponent).context("match not found - bad user id?")?;
+        if *current != event.new_result {
+            return Err(anyhow!("the result has changed since that report, report the correct score instead"));
+        }
+        //Later rounds were paired on this result, so it can be corrected but not taken back
+        if !event.old_result.is_played() && matrix.core.swiss.as_ref().is_some_and(|x| !x.in_current_round(event.player, event.opponent)) {
+            return Err(anyhow!("the next round has been paired since that report, report the correct score instead"));
+        }
+        matrix.core.set_result(event.player, event.opponent, event.old_result.clone())?;
+        matrix.history.push(ReportEvent{reporter: user.id, player: event.player, opponent: event.opponent,
+            old_result: event.new_result.clone(), new_result: event.old_result.clone(), timestamp: Timestamp::now(), interaction, undo_of: Some(event_index), note: None});
+        discord.say(matrix.thread, format!("{} undid the report of {} {} {}, restoring {}", user, matrix.core.name_of(event.player), event.new_result.to_str(),
+            matrix.core.name_of(event.opponent), event.old_result.to_str())).await?;
 
-    async fn end(&self, ctx: &Context, command: &CommandInteraction) -> Result<String>{
+        Self::update_grid(discord, matrix).await?;
+        Ok("Success".to_string())
+    }
+
+    async fn dispute(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
+        let options = &command.data.options();
+        let guild = command.guild_id.context("guild not found for dispute")?;
+        let Some(mut match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
+        let Some(ResolvedValue::User(opponent, _)) = find_option(options, "opponent") else {return Err(anyhow!("opponent not found in dispute"));};
+        let player = match find_option(options, "player") {
+            Some(ResolvedValue::User(player, _)) => player,
+            _ => &command.user
+        };
+        let reason = match find_option(options, "reason") {
+            Some(ResolvedValue::String(reason)) => reason.to_string(),
+            _ => "no reason given".to_string(),
+        };
+        let matrix = match_data_list.get_mut().values_mut().find(|x| x.thread == command.channel_id).context("Attempted to dispute but results thread not found")?;
+        let player = lookup_userid(player.id, &matrix.core.users).context("User not found")?;
+        let opponent = lookup_userid(opponent.id, &matrix.core.users).context("User not found")?;
+        if player.id == opponent.id {
+            return Err(anyhow!("trying to dispute a match against the same player"));
+        }
+        if ![player.id, opponent.id].contains(&player_id(command.user.id)) && !matrix.is_organizer(command.user.id, command.member.as_deref()) {
+            return Err(anyhow!("only the players in a match or an organizer can dispute it"));
+        }
+        let (home, away, _) = matrix.core.match_key(player.id, opponent.id, find_leg(options)?)?;
+        if matrix.is_disputed(home, away) {
+            return Err(anyhow!("the match between {} and {} is already disputed", player.name, opponent.name));
+        }
+        for report in matrix.take_pending(home, away){
+            Self::close_pending(discord, matrix, &report).await?;
+        }
+        Self::raise_dispute(discord, matrix, home, away, &command.user, reason).await?;
+        Ok("Success - the match is locked until an organizer resolves it".to_string())
+    }
+    //Flags the match as disputed and asks the organizer to settle it
+    async fn raise_dispute(discord: &dyn Discord, matrix: &mut MatchMatrix, player: PlayerId, opponent: PlayerId, raised_by: &User, reason: String) -> Result<()>{
+        let current = matrix.core.result(player, opponent).context("match not found - bad user id?")?.clone();
+        let name_of = |id| matrix.core.name_of(id);
+        let home = if matrix.core.double {format!(" at {}'s home", name_of(player))} else {String::new()};
+        let organizer = matrix.organizer.map_or("A moderator".to_string(), |x| format!("<@{}>", x));
+        discord.send_message(matrix.thread, CreateMessage::new()
+            .allowed_mentions(CreateAllowedMentions::new().users(matrix.organizer))
+            .content(format!("{} disputes the match {} vs {}{}, currently {} ({}). {} can settle it with /resolve", raised_by, name_of(player), name_of(opponent),
+                home, current.to_str(), reason, organizer)))
+            .await?;
+        matrix.disputes.push(Dispute{player, opponent, raised_by: raised_by.id, reason, timestamp: Timestamp::now()});
+        Self::update_grid(discord, matrix).await
+    }
+    async fn resolve(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
+        let options = &command.data.options();
+        let guild = command.guild_id.context("guild not found for resolve")?;
+        let Some(mut match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
+        let Some(ResolvedValue::User(player, _)) = find_option(options, "player") else {return Err(anyhow!("player not found in resolve"));};
+        let Some(ResolvedValue::User(opponent, _)) = find_option(options, "opponent") else {return Err(anyhow!("opponent not found in resolve"));};
+        let Some(ResolvedValue::String(result_str)) = find_option(options, "score") else {return Err(anyhow!("score not found in resolve"));};
+        let Some(ResolvedValue::String(note)) = find_option(options, "note") else {return Err(anyhow!("note not found in resolve"));};
+        let matrix = match_data_list.get_mut().values_mut().find(|x| x.thread == command.channel_id).context("Attempted to resolve but results thread not found")?;
+        if !matrix.is_organizer(command.user.id, command.member.as_deref()) {
+            return Err(anyhow!("only an organizer can resolve disputes"));
+        }
+        let player = lookup_userid(player.id, &matrix.core.users).context("User not found")?;
+        let opponent = lookup_userid(opponent.id, &matrix.core.users).context("User not found")?;
+        let format = matrix.core.format;
+        let result = MatchResult::get(result_str).filter(|x| format.allows(x))
+            .context(format!("{} is not a valid score for a {} match", result_str, format.description()))?;
+        let (player_id, opponent_id, away) = matrix.core.match_key(player.id, opponent.id, find_leg(options)?)?;
+        let double = matrix.core.double;
+        let index = matrix.disputes.iter().position(|x| same_match(double, (x.player, x.opponent), (player_id, opponent_id)))
+            .context(format!("the match between {} and {} isn't disputed", player.name, opponent.name))?;
+        matrix.disputes.remove(index);
+        let old_result = matrix.core.result(player_id, opponent_id).context("match not found - bad user id?")?.clone();
+        let announcement = format!("{} resolved the dispute as {} {} {} ({})", command.user, player.name, result_str, opponent.name, note);
+        Self::commit_report(discord, matrix, ReportEvent{reporter: command.user.id, player: player_id, opponent: opponent_id, old_result,
+            new_result: if away {result.invert()} else {result}, timestamp: Timestamp::now(), interaction: command.id, undo_of: None,
+            note: Some(note.to_string())}, announcement).await?;
+        Ok("Success".to_string())
+    }
+
+    //Redraws the grid after players joined or left, which can change how many messages it needs
+    async fn resize_grid(discord: &dyn Discord, matrix: &mut MatchMatrix) -> Result<()>{
+        let needed = matrix.core.message_count();
+        if needed < matrix.mainposts.len() {
+            for post in matrix.mainposts.split_off(needed){
+                discord.delete_message(matrix.thread, post).await?;
+            }
+        } else if needed > matrix.mainposts.len() {
+            //Nothing can be posted above the reports, so the grid, legend and image move to the end of the thread
+            for post in matrix.mainposts.drain(..).chain(matrix.legend_post).chain(matrix.image_post){
+                discord.delete_message(matrix.thread, post).await?;
+            }
+            for msg in render_grid(&matrix.core, &matrix.header(), &matrix.theme, &matrix.marks(), true, needed)?{
+                matrix.mainposts.push(discord.say(matrix.thread, msg).await?);
+            }
+            matrix.legend_post = Some(discord.say(matrix.thread, matrix.legend()).await?);
+            if matrix.image_post.is_some() {
+                matrix.image_post = Some(discord.send_file(matrix.thread, GRID_IMAGE, matrix.render_png()).await?);
+            }
+            return Ok(());
+        }
+        Self::update_grid(discord, matrix).await
+    }
+    async fn update_grid(discord: &dyn Discord, matrix: &MatchMatrix) -> Result<()>{
+        let messages = matrix.render_grid(true)?;
+        for (msg, post) in messages.iter().zip(&matrix.mainposts){
+            discord.edit_message(matrix.thread, *post, EditMessage::new().content(msg)).await?;
+        }
+        if let Some(post) = matrix.image_post {
+            discord.replace_file(matrix.thread, post, GRID_IMAGE, matrix.render_png()).await?;
+        }
+        Ok(())
+    }
+
+    async fn end(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
         let options = &command.data.options();
         let guild = command.guild_id.context("guild not found for end")?;
         let Some(mut match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
@@ -342,22 +1371,203 @@ impl Handler{
             value: ResolvedValue::String(commandshortname), ..
         }) = options.get(0) else {return Err(anyhow!("name not found in end setup"));};
 
+        if let Some(mut bracket_list) = self.bracket_data.get_async(&guild).await{
+            if let Some(bracket) = bracket_list.get_mut().remove(*commandshortname){
+                for msg in bracket.render(){
+                    discord.say(command.channel_id, msg).await?;
+                }
+                Self::reset_tournament_commands(discord, &guild, match_data_list, bracket_list.get()).await?;
+                return Ok("Success".to_string());
+            }
+        }
+
         let matchup = match_data_list.get(*commandshortname).context(format!("unable to find given name {} in match list", commandshortname))?;
-        let messages = render_grid(&matchup.users, &matchup.results, &HashSet::new(), &matchup.threadname, matchup.mainposts.len())?;
+        let messages = matchup.render_grid(false)?;
         for msg in messages{
-            command.channel_id.say(&ctx.http, msg).await?;
+            discord.say(command.channel_id, msg).await?;
+        }
+        if matchup.image_post.is_some() {
+            discord.send_file(command.channel_id, GRID_IMAGE, matchup.render_png()).await?;
+        }
+        for msg in split_message(&standings::render(&matchup.threadname, &matchup.core, &standings::compute(&matchup.core))){
+            discord.say(command.channel_id, msg).await?;
         }
         match_data_list.remove(*commandshortname);
-        Self::reset_tournament_commands(ctx, &guild, &match_data_list).await?;
+        self.reset_commands(discord, &guild, match_data_list).await?;
         Ok("Success".to_string())
     }
 
-    async fn reset_tournament_commands(ctx: &Context, guild: &GuildId, tournaments: &HashMap<String, MatchMatrix>) -> Result<()>{
+    async fn playoffs(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
+        let options = &command.data.options();
+        let guild = command.guild_id.context("guild not found for playoffs")?;
+        let Some(match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
+        let match_data_list = match_data_list.get();
+
+        let Some(ResolvedValue::String(commandshortname)) = find_option(options, "tournament") else {return Err(anyhow!("tournament not found in playoffs"));};
+        let Some(ResolvedValue::String(shortname)) = find_option(options, "cmd") else {return Err(anyhow!("command name not found in playoffs"));};
+        let Some(ResolvedValue::Integer(size)) = find_option(options, "size") else {return Err(anyhow!("size not found in playoffs"));};
+        let double = matches!(find_option(options, "elimination"), Some(ResolvedValue::String("double")));
+        let shortname = shortname.to_lowercase();
+        static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[-_\p{L}\p{N}\p{sc=Deva}\p{sc=Thai}]{1,32}$").unwrap());
+        if !RE.is_match(&shortname) {return Err(anyhow!("invalid command name"))};
+
+        let matrix = match_data_list.get(*commandshortname).context("unable to find given name in match list")?;
+        let size = usize::try_from(*size).ok().filter(|x| (2..=matrix.core.users.len()).contains(x))
+            .context(format!("size must be between 2 and the {} players in the tournament", matrix.core.users.len()))?;
+        let threadname = match find_option(options, "title") {
+            Some(ResolvedValue::String(title)) => title.to_string(),
+            _ => format!("{} playoffs", matrix.threadname),
+        };
+        let seeds = standings::compute(&matrix.core).iter().take(size).filter_map(|x| matrix.core.player(x.user).cloned()).collect();
+        let mut bracket_list = self.bracket_data.entry_async(guild).await.or_insert(HashMap::new());
+        if match_data_list.contains_key(&shortname) || bracket_list.get().contains_key(&shortname) {
+            return Err(anyhow!("the command /{} is already in use", shortname));
+        }
+
+        let thread = discord.create_thread(command.channel_id, &threadname).await?;
+        let mut bracket = PlayoffBracket{thread, threadname, mainposts: Vec::new(), bracket: Bracket::new(seeds, matrix.core.format, double)?,
+            policy: matrix.policy, audit_channel: matrix.audit_channel};
+        let mut initial_message_str = String::new();
+        for user in &bracket.bracket.users{
+            initial_message_str += &format!("<@{}> ", user.id);
+        }
+        discord.send_message(thread, CreateMessage::new()
+            .allowed_mentions(CreateAllowedMentions::new().users(bracket.bracket.users.iter().map(|x| user_id(x.id))))
+            .flags(MessageFlags::SUPPRESS_NOTIFICATIONS)
+            .content(format!("{} Report your results here using the command /{} or /result ({}, {} elimination)", initial_message_str, shortname,
+                bracket.bracket.format.name(), if double {"double"} else {"single"})))
+            .await?;
+        for msg in bracket.bracket.render(&bracket.threadname, bracket.bracket.message_count()){
+            bracket.mainposts.push(discord.say(thread, msg).await?);
+        }
+
+        bracket_list.get_mut().insert(shortname, bracket);
+        Self::reset_tournament_commands(discord, &guild, match_data_list, bracket_list.get()).await?;
+        Ok("Success!".to_string())
+    }
+
+    async fn report_bracket_result(discord: &dyn Discord, bracket: &mut PlayoffBracket, player: &Player, result_str: &str, opponent: &Player, command: &CommandInteraction) -> Result<String>{
+        let reporter_user = &command.user;
+        if !bracket.policy.allows(reporter_user.id, is_moderator(command.member.as_deref()), player.id, opponent.id) {
+            audit(discord, bracket.audit_channel, format!("Rejected report in {} from {}: {} {} {}", bracket.threadname, reporter_user, player.name, result_str, opponent.name)).await;
+            return Err(anyhow!("You can't report this match, in {} {}", bracket.threadname, bracket.policy.description()));
+        }
+        let result = MatchResult::get(result_str).context(format!("{} is not a valid score", result_str))?;
+        //Moderators can correct a decided match until a match it fed into is played
+        if bracket.bracket.find_match(player.id, opponent.id).is_none() && is_moderator(command.member.as_deref()) {
+            bracket.bracket.rereport(player.id, opponent.id, result, player_id(reporter_user.id))?;
+            discord.say(bracket.thread, format!("{} corrects {} {} {}", reporter_user, player.name, result_str, opponent.name)).await?;
+        } else {
+            bracket.bracket.report(player.id, opponent.id, result, player_id(reporter_user.id))?;
+            discord.say(bracket.thread, format!("{} reports {} {} {}", reporter_user, player.name, result_str, opponent.name)).await?;
+        }
+        Self::update_bracket(discord, bracket).await?;
+        if let Some(champion) = bracket.bracket.champion() {
+            discord.say(bracket.thread, format!(":trophy: Congratulations <@{}>, winner of {}!", champion, bracket.threadname)).await?;
+        }
+        Ok("Success".to_string())
+    }
+    async fn undo_bracket(discord: &dyn Discord, bracket: &mut PlayoffBracket, user: &User, is_moderator: bool) -> Result<String>{
+        //Moderators undo the latest report, everyone else their own latest report
+        let index = bracket.bracket.reports.iter().rev().copied()
+            .find(|x| is_moderator || bracket.bracket.matches[*x].reporter == Some(player_id(user.id)))
+            .context("no report found that you can undo")?;
+        let undone = bracket.bracket.match_line(&bracket.bracket.matches[index]);
+        bracket.bracket.undo(index)?;
+        discord.say(bracket.thread, format!("{} undid the report of {}", user, undone)).await?;
+        Self::update_bracket(discord, bracket).await?;
+        Ok("Success".to_string())
+    }
+    async fn update_bracket(discord: &dyn Discord, bracket: &PlayoffBracket) -> Result<()>{
+        for (msg, post) in bracket.render().iter().zip(&bracket.mainposts){
+            discord.edit_message(bracket.thread, *post, EditMessage::new().content(msg)).await?;
+        }
+        Ok(())
+    }
+
+    async fn standings(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
+        let options = &command.data.options();
+        let guild = command.guild_id.context("guild not found for standings")?;
+        let Some(match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
+        let match_data_list = match_data_list.get();
+
+        let Some(ResolvedOption {
+            value: ResolvedValue::String(commandshortname), ..
+        }) = options.get(0) else {return Err(anyhow!("name not found in standings"));};
+
+        let matchup = match_data_list.get(*commandshortname).context("unable to find given name in match list")?;
+        for msg in split_message(&standings::render(&matchup.threadname, &matchup.core, &standings::compute(&matchup.core))){
+            discord.say(command.channel_id, msg).await?;
+        }
+        Ok("Success".to_string())
+    }
+
+    //Opponent choices for tournaments with too many players to list them all
+    async fn autocomplete_opponent(&self, autocomplete: &CommandInteraction) -> Result<CreateAutocompleteResponse>{
+        let guild = autocomplete.guild_id.context("guild not found for autocomplete")?;
+        let typed = autocomplete.data.autocomplete().context("no option being typed")?.value.to_lowercase();
+        let Some(match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
+        let bracket_list = self.bracket_data.get_async(&guild).await;
+        //Suggest the current Swiss or bracket opponent first
+        let (all_users, current_opponent) = match match_data_list.get().get(&autocomplete.data.name) {
+            Some(matrix) => (&matrix.core.users, matrix.core.swiss.as_ref().and_then(|x| x.current_opponent(player_id(autocomplete.user.id)))),
+            None => {
+                let bracket = bracket_list.as_ref().and_then(|x| x.get().get(&autocomplete.data.name)).context("unable to find given name in match list")?;
+                (&bracket.bracket.users, bracket.bracket.current_opponent(player_id(autocomplete.user.id)))
+            },
+        };
+        let mut users: Vec<&Player> = all_users.iter().filter(|x| x.id != player_id(autocomplete.user.id) && x.name.to_lowercase().contains(&typed)).collect();
+        users.sort_by_key(|x| Some(x.id) != current_opponent);
+        let mut response = CreateAutocompleteResponse::new();
+        for user in users.into_iter().take(MAX_CHOICES){
+            response = response.add_string_choice(&user.name, user.id.to_string());
+        }
+        Ok(response)
+    }
+
+    //Names of the guild's player groups for /fam and /group
+    async fn autocomplete_group(&self, autocomplete: &CommandInteraction) -> Result<CreateAutocompleteResponse>{
+        let guild = autocomplete.guild_id.context("guild not found for autocomplete")?;
+        let typed = autocomplete.data.autocomplete().context("no option being typed")?.value.to_lowercase();
+        let mut response = CreateAutocompleteResponse::new();
+        if let Some(config) = self.config_data.get_async(&guild).await{
+            for name in config.get().groups.keys().filter(|x| x.contains(&typed)).take(MAX_CHOICES){
+                response = response.add_string_choice(name, name);
+            }
+        }
+        Ok(response)
+    }
+
+    //Names of the setups in progress for /add, /create, /cancel and /signup
+    async fn autocomplete_draft(&self, autocomplete: &CommandInteraction) -> Result<CreateAutocompleteResponse>{
+        let guild = autocomplete.guild_id.context("guild not found for autocomplete")?;
+        let typed = autocomplete.data.autocomplete().context("no option being typed")?.value.to_lowercase();
+        let mut response = CreateAutocompleteResponse::new();
+        if let Some(setups) = self.setup_data.get_async(&guild).await{
+            let mut names: Vec<&String> = setups.get().keys().filter(|x| x.contains(&typed)).collect();
+            names.sort();
+            for name in names.into_iter().take(MAX_CHOICES){
+                response = response.add_string_choice(name, name);
+            }
+        }
+        Ok(response)
+    }
+
+    async fn reset_tournament_commands(discord: &dyn Discord, guild: &GuildId, tournaments: &HashMap<String, MatchMatrix>, brackets: &HashMap<String, PlayoffBracket>) -> Result<()>{
         //Returns the delta in number of tournament report commands
         let mut fam_user_options = CreateCommandOption::new(CommandOptionType::String, "tournament", "Ping which opponents").required(true);
         let mut findable_user_options = CreateCommandOption::new(CommandOptionType::String, "tournament", "Which tournaments to enable/disable Find A Match pings?").required(true);
         let mut ping_user_options = CreateCommandOption::new(CommandOptionType::String, "tournament", "Ping a tournament").required(true);
         let mut end_user_options = CreateCommandOption::new(CommandOptionType::String, "tournament", "The tournament to end").required(true);
+        let mut history_user_options = CreateCommandOption::new(CommandOptionType::String, "tournament", "Which tournament's reports to list").required(true);
+        let mut standings_user_options = CreateCommandOption::new(CommandOptionType::String, "tournament", "Which tournament's standings to post").required(true);
+        let mut export_user_options = CreateCommandOption::new(CommandOptionType::String, "tournament", "Which tournament's results to export").required(true);
+        let mut deadline_user_options = CreateCommandOption::new(CommandOptionType::String, "tournament", "Which tournament's deadline to set").required(true);
+        let mut import_user_options = CreateCommandOption::new(CommandOptionType::String, "tournament", "Which tournament to import results into").required(true);
+        let mut addplayer_user_options = CreateCommandOption::new(CommandOptionType::String, "tournament", "Which tournament to add the player to").required(true);
+        let mut removeplayer_user_options = CreateCommandOption::new(CommandOptionType::String, "tournament", "Which tournament to remove the player from").required(true);
+        let mut withdraw_user_options = CreateCommandOption::new(CommandOptionType::String, "tournament", "Which tournament the player is leaving").required(true);
+        let mut next_round_user_options = CreateCommandOption::new(CommandOptionType::String, "tournament", "The Swiss tournament to advance").required(true);
         fam_user_options = fam_user_options.add_string_choice("All tournaments", "");
         findable_user_options = findable_user_options.add_string_choice("All tournaments", "");
         for (shortname, longname) in tournaments.iter().map(|(key, val)| (key, &val.threadname)){
@@ -365,43 +1575,372 @@ impl Handler{
             findable_user_options = findable_user_options.add_string_choice(longname, shortname);
             ping_user_options = ping_user_options.add_string_choice(longname, shortname);
             end_user_options = end_user_options.add_string_choice(longname, shortname);
+            history_user_options = history_user_options.add_string_choice(longname, shortname);
+            standings_user_options = standings_user_options.add_string_choice(longname, shortname);
+            export_user_options = export_user_options.add_string_choice(longname, shortname);
+            deadline_user_options = deadline_user_options.add_string_choice(longname, shortname);
+        }
+        let mut playoffs_user_options = CreateCommandOption::new(CommandOptionType::String, "tournament", "Seed the playoffs from which tournament's standings").required(true);
+        for (shortname, longname) in tournaments.iter().map(|(key, val)| (key, &val.threadname)){
+            playoffs_user_options = playoffs_user_options.add_string_choice(longname, shortname);
+        }
+        for (shortname, bracket) in brackets{
+            end_user_options = end_user_options.add_string_choice(&bracket.threadname, shortname);
+        }
+        for (shortname, tournament_matrix) in tournaments.iter().filter(|(_, x)| x.core.swiss.is_none()){
+            import_user_options = import_user_options.add_string_choice(&tournament_matrix.threadname, shortname);
+            addplayer_user_options = addplayer_user_options.add_string_choice(&tournament_matrix.threadname, shortname);
+            removeplayer_user_options = removeplayer_user_options.add_string_choice(&tournament_matrix.threadname, shortname);
+            withdraw_user_options = withdraw_user_options.add_string_choice(&tournament_matrix.threadname, shortname);
+        }
+        for (shortname, tournament_matrix) in tournaments.iter().filter(|(_, x)| x.core.swiss.is_some()){
+            next_round_user_options = next_round_user_options.add_string_choice(&tournament_matrix.threadname, shortname);
         }
         let findable_enable_option = CreateCommandOption::new(CommandOptionType::Integer, "enable", "Do you want to allow Find A Match pings (on) or prevent them (off)?")
             .required(true).add_int_choice("on", 1).add_int_choice("off", 0);
-        let restrict_fam_ping = CreateCommandOption::new(CommandOptionType::Integer, "exclude", "Don't ping a given group of players")
-            .add_int_choice("our strongest players", 1).add_int_choice("everybody else", 2);
+        let exclude_fam_group = CreateCommandOption::new(CommandOptionType::String, "exclude", "Don't ping the players in this group")
+            .set_autocomplete(true);
+        let include_fam_group = CreateCommandOption::new(CommandOptionType::String, "include", "Only ping the players in this group")
+            .set_autocomplete(true);
         let fam_noping = CreateCommandOption::new(CommandOptionType::Boolean, "text-only", "Don't ping, print names only");
 
         let mut commands = vec![
             CreateCommand::new("fam").description("Find A Match: Ping other players that you haven't played yet")
-                .add_option(fam_user_options).add_option(fam_noping).add_option(restrict_fam_ping),
+                .add_option(fam_user_options).add_option(fam_noping).add_option(exclude_fam_group).add_option(include_fam_group),
             CreateCommand::new("matchpings").description("Enable or disable pinging for Find A Match")
                 .add_option(findable_user_options).add_option(findable_enable_option),
             CreateCommand::new("ping").description("Silent ping all players of a tournament")
                 .default_member_permissions(Permissions::MODERATE_MEMBERS).add_option(ping_user_options),            
             CreateCommand::new("end").description("End a match matrix, posting final results in this channel")
-                .default_member_permissions(Permissions::MODERATE_MEMBERS).add_option(end_user_options)
+                .default_member_permissions(Permissions::MODERATE_MEMBERS).add_option(end_user_options),
+            CreateCommand::new("history").description("List the result reports made in a tournament")
+                .add_option(history_user_options)
+                .add_option(CreateCommandOption::new(CommandOptionType::User, "player", "Only list reports involving this player"))
+                .add_option(CreateCommandOption::new(CommandOptionType::User, "opponent", "Only list reports between the player and this opponent")),
+            CreateCommand::new("standings").description("Post the current standings of a tournament")
+                .add_option(standings_user_options),
+            CreateCommand::new("export").description("Attach a tournament's results as CSV and JSON files")
+                .add_option(export_user_options),
+            CreateCommand::new("deadline").description("Set when a tournament's matches should be played by, with reminders before it")
+                .default_member_permissions(Permissions::MODERATE_MEMBERS).add_option(deadline_user_options)
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "date", "UTC date like 2025-06-30, or date and time like 2025-06-30 18:00 (leave out to clear)"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "reminders", "Days before the deadline to remind players, separated by commas (default 7, 3, 1)")),
+            CreateCommand::new("playoffs").description("Start an elimination bracket in a new thread, seeded from a tournament's standings")
+                .default_member_permissions(Permissions::MODERATE_MEMBERS)
+                .add_option(playoffs_user_options)
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "cmd", "The new command-name for results (lower case, no spaces)").required(true))
+                .add_option(CreateCommandOption::new(CommandOptionType::Integer, "size", "How many of the top players qualify").required(true)
+                    .min_int_value(2).max_int_value(64))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "elimination", "Single (default) or double elimination")
+                    .add_string_choice("Single", "single").add_string_choice("Double", "double"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "title", "The name of the thread to make (default the tournament name + playoffs)"))
             ];
+        if tournaments.values().any(|x| x.core.swiss.is_none()){
+            commands.push(CreateCommand::new("import").description("Record results from an attached CSV of player, opponent and score rows")
+                .default_member_permissions(Permissions::MODERATE_MEMBERS).add_option(import_user_options)
+                .add_option(CreateCommandOption::new(CommandOptionType::Attachment, "file", "CSV with a player,opponent,score row per match (you can attach an /export)").required(true)));
+            commands.push(CreateCommand::new("addplayer").description("Add a player to a running round-robin")
+                .default_member_permissions(Permissions::MODERATE_MEMBERS).add_option(addplayer_user_options)
+                .add_option(CreateCommandOption::new(CommandOptionType::User, "player", "The player to add").required(true)));
+            commands.push(CreateCommand::new("removeplayer").description("Remove a player and their results from a running round-robin")
+                .default_member_permissions(Permissions::MODERATE_MEMBERS).add_option(removeplayer_user_options)
+                .add_option(CreateCommandOption::new(CommandOptionType::User, "player", "The player to remove").required(true)));
+            commands.push(CreateCommand::new("withdraw").description("Mark a player as withdrawn, voiding or forfeiting their matches")
+                .default_member_permissions(Permissions::MODERATE_MEMBERS).add_option(withdraw_user_options)
+                .add_option(CreateCommandOption::new(CommandOptionType::User, "player", "The player who is leaving").required(true)));
+        }
+        if tournaments.values().any(|x| x.core.swiss.is_some()){
+            commands.push(CreateCommand::new("nextround").description("Pair the next Swiss round now, even if matches are unreported")
+                .default_member_permissions(Permissions::MODERATE_MEMBERS).add_option(next_round_user_options));
+        }
 
         for (shortname, tournament_matrix) in tournaments.iter(){
             let mut player_options = CreateCommandOption::new(CommandOptionType::String, "opponent", "Who was your opponent").required(true);
-            for user in &tournament_matrix.users{
-                player_options = player_options.add_string_choice(&user.name, user.id.to_string());
+            if tournament_matrix.core.users.len() > MAX_CHOICES {
+                player_options = player_options.set_autocomplete(true);
+            } else {
+                for user in &tournament_matrix.core.users{
+                    player_options = player_options.add_string_choice(&user.name, user.id.to_string());
+                }
             }
-            commands.push(CreateCommand::new(shortname)
+            let mut command = CreateCommand::new(shortname)
             .description(format!("Submit result for {}", &tournament_matrix.threadname))
-            .add_option(
-                CreateCommandOption::new(CommandOptionType::String, "score", "What was the match score (you first)")
-                .add_string_choice("2-0 (Win)", "2-0").add_string_choice("2-1 (Win)", "2-1")
-                .add_string_choice("1-2 (Loss)", "1-2").add_string_choice("0-2 (Loss)", "0-2")
-                .add_string_choice("0-0 (No result)", "0-0").required(true)
-            ).add_option(player_options));
-        }
-        guild.set_commands(&ctx.http, commands).await?;
+            .add_option(score_option(tournament_matrix.core.format, "What was the match score (you first)"))
+            .add_option(player_options);
+            if tournament_matrix.core.double {
+                command = command.add_option(leg_option());
+            }
+            commands.push(command);
+        }
+        for (shortname, bracket) in brackets{
+            let mut player_options = CreateCommandOption::new(CommandOptionType::String, "opponent", "Who was your opponent").required(true);
+            if bracket.bracket.users.len() > MAX_CHOICES {
+                player_options = player_options.set_autocomplete(true);
+            } else {
+                for user in &bracket.bracket.users{
+                    player_options = player_options.add_string_choice(&user.name, user.id.to_string());
+                }
+            }
+            commands.push(CreateCommand::new(shortname)
+            .description(format!("Submit result for {}", &bracket.threadname))
+            .add_option(decisive_score_option(bracket.bracket.format, "What was the match score (you first)"))
+            .add_option(player_options));
+        }
+        discord.set_guild_commands(*guild, commands).await?;
         Ok(())
     }
 
-    async fn ping(&self, ctx: &Context, command: &CommandInteraction) -> Result<String>{
+    async fn export(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
+        let options = &command.data.options();
+        let guild = command.guild_id.context("guild not found for export")?;
+        let Some(match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
+        let match_data_list = match_data_list.get();
+
+        let Some(ResolvedValue::String(commandshortname)) = find_option(options, "tournament") else {return Err(anyhow!("tournament not found in export"));};
+        let matchup = match_data_list.get(*commandshortname).context("unable to find given name in match list")?;
+        let reports: Vec<Report> = matchup.history.iter().map(|x| Report{player: x.player, opponent: x.opponent,
+            reporter: player_id(x.reporter), time: x.timestamp.to_string()}).collect();
+        let matches = export::played_matches(&matchup.core, &reports);
+        let json = export::json(&matchup.threadname, &matchup.core, &matches, &standings::compute(&matchup.core));
+        discord.send_files(command.channel_id, vec![
+            (format!("{}.csv", commandshortname), export::csv(&matchup.core, &matches).into_bytes()),
+            (format!("{}.json", commandshortname), serde_json::to_vec_pretty(&json)?),
+        ]).await?;
+        Ok(format!("Exported {} played matches", matches.len()))
+    }
+
+    async fn change_players(&self, discord: &dyn Discord, command: &CommandInteraction, add: bool) -> Result<String>{
+        let options = &command.data.options();
+        let guild = command.guild_id.context("guild not found for changing players")?;
+        let Some(mut match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
+
+        let Some(ResolvedValue::String(commandshortname)) = find_option(options, "tournament") else {return Err(anyhow!("tournament not found in changing players"));};
+        let Some(ResolvedValue::User(user, _)) = find_option(options, "player") else {return Err(anyhow!("player not found in changing players"));};
+        let matrix = match_data_list.get_mut().get_mut(*commandshortname).context("unable to find given name in match list")?;
+        if !matrix.is_organizer(command.user.id, command.member.as_deref()) {
+            return Err(anyhow!("only an organizer can add or remove players"));
+        }
+        //Swiss pairings are made round by round from the players there were at the start
+        if matrix.core.swiss.is_some() {
+            return Err(anyhow!("players can only be added to or removed from a round-robin tournament"));
+        }
+        let announcement = if add {
+            let player = localize_user(user, discord, guild).await?;
+            let announcement = format!("{} added {} to the tournament", command.user, player.name);
+            matrix.core.add_player(player)?;
+            announcement
+        } else {
+            let id = player_id(user.id);
+            if matrix.core.users.len() <= 2 {
+                return Err(anyhow!("a tournament needs at least 2 players, use /end to finish it instead"));
+            }
+            let played = matrix.core.player_results(id).filter(|(_, x)| x.is_played()).count();
+            let player = matrix.core.remove_player(id)?;
+            //Their unsettled matches go with them
+            let involved = |a: PlayerId, b: PlayerId| a == id || b == id;
+            let pending: Vec<PendingReport> = matrix.pending.extract_if(.., |x| involved(x.player, x.opponent)).collect();
+            for report in pending{
+                Self::close_pending(discord, matrix, &report).await?;
+            }
+            matrix.disputes.retain(|x| !involved(x.player, x.opponent));
+            format!("{} removed {} from the tournament, along with their {} played matches", command.user, player.name, played)
+        };
+
+        //The intro lists the players, which is also how /reprocess finds them
+        let messages = discord.messages(matrix.thread).await?;
+        let intro = messages.last().context("intro message not found")?;
+        let intro_content = Intro{players: matrix.core.users.iter().map(|x| x.id).collect(), ..Intro::parse(&intro.content)?}.render();
+        discord.edit_message(matrix.thread, intro.id, EditMessage::new().content(intro_content)).await?;
+        Self::resize_grid(discord, matrix).await?;
+        discord.say(matrix.thread, announcement.clone()).await?;
+        self.reset_commands(discord, &guild, match_data_list.get()).await?;
+        Ok(announcement)
+    }
+
+    async fn set_deadline(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
+        let options = &command.data.options();
+        let guild = command.guild_id.context("guild not found for deadline")?;
+        let Some(mut match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
+
+        let Some(ResolvedValue::String(commandshortname)) = find_option(options, "tournament") else {return Err(anyhow!("tournament not found in deadline"));};
+        let matrix = match_data_list.get_mut().get_mut(*commandshortname).context("unable to find given name in match list")?;
+        if !matrix.is_organizer(command.user.id, command.member.as_deref()) {
+            return Err(anyhow!("only an organizer can set the deadline"));
+        }
+        let deadline = match find_option(options, "date") {
+            Some(ResolvedValue::String(date)) => Some(parse_deadline(date)?),
+            _ => None,
+        };
+        if deadline.is_some_and(|x| x.unix_timestamp() <= Timestamp::now().unix_timestamp()) {
+            return Err(anyhow!("the deadline has to be in the future"));
+        }
+        let reminders = match find_option(options, "reminders") {
+            Some(ResolvedValue::String(days)) if deadline.is_some() => parse_reminders(days)?,
+            Some(_) => return Err(anyhow!("reminders can only be given along with a date")),
+            None if deadline.is_some() => vec![7, 3, 1],
+            None => Vec::new(),
+        };
+        let announcement = match deadline {
+            Some(deadline) => format!("{} set the deadline to <t:{}:f>, with reminders {} days before", command.user, deadline.unix_timestamp(),
+                reminders.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", ")),
+            None => format!("{} cleared the deadline", command.user),
+        };
+        matrix.deadline = deadline;
+        matrix.reminders = reminders;
+        matrix.reminders_sent.clear();
+        discord.say(matrix.thread, announcement.clone()).await?;
+        Self::update_grid(discord, matrix).await?;
+        Ok(announcement)
+    }
+
+    async fn withdraw(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
+        let options = &command.data.options();
+        let guild = command.guild_id.context("guild not found for withdraw")?;
+        let Some(mut match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
+
+        let Some(ResolvedValue::String(commandshortname)) = find_option(options, "tournament") else {return Err(anyhow!("tournament not found in withdraw"));};
+        let Some(ResolvedValue::User(user, _)) = find_option(options, "player") else {return Err(anyhow!("player not found in withdraw"));};
+        let matrix = match_data_list.get_mut().get_mut(*commandshortname).context("unable to find given name in match list")?;
+        if !matrix.is_organizer(command.user.id, command.member.as_deref()) {
+            return Err(anyhow!("only an organizer can withdraw players"));
+        }
+        //Swiss pairings would need a bye for whoever was due to play them
+        if matrix.core.swiss.is_some() {
+            return Err(anyhow!("players can only withdraw from a round-robin tournament"));
+        }
+        let id = player_id(user.id);
+        let changed = matrix.core.withdraw(id, matrix.withdrawals)?;
+        //Their unsettled matches are covered by the withdrawal
+        let involved = |x: PlayerId, y: PlayerId| x == id || y == id;
+        let pending: Vec<PendingReport> = matrix.pending.extract_if(.., |x| involved(x.player, x.opponent)).collect();
+        for report in pending{
+            Self::close_pending(discord, matrix, &report).await?;
+        }
+        matrix.disputes.retain(|x| !involved(x.player, x.opponent));
+        let outcome = match matrix.withdrawals {
+            Withdrawal::Void => format!("all {} of their matches are void {}", changed, matrix.theme.unplayable),
+            Withdrawal::Forfeit => format!("their {} unplayed matches are forfeited {}", changed, matrix.theme.forfeit),
+        };
+        let announcement = format!("{} withdrew {} from the tournament, {}", command.user, matrix.core.name_of(id), outcome);
+        discord.say(matrix.thread, announcement.clone()).await?;
+        Self::update_grid(discord, matrix).await?;
+        Ok(announcement)
+    }
+
+    async fn import(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
+        let options = &command.data.options();
+        let guild = command.guild_id.context("guild not found for import")?;
+        let Some(mut match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
+
+        let Some(ResolvedValue::String(commandshortname)) = find_option(options, "tournament") else {return Err(anyhow!("tournament not found in import"));};
+        let Some(ResolvedValue::Attachment(file)) = find_option(options, "file") else {return Err(anyhow!("file not found in import"));};
+        let matrix = match_data_list.get_mut().get_mut(*commandshortname).context("unable to find given name in match list")?;
+        if !matrix.is_organizer(command.user.id, command.member.as_deref()) {
+            return Err(anyhow!("only an organizer can import results"));
+        }
+        //Swiss results have to follow the pairings, which are made round by round
+        if matrix.core.swiss.is_some() {
+            return Err(anyhow!("results can only be imported into a round-robin tournament"));
+        }
+        let text = String::from_utf8(discord.download(file).await?).context(format!("{} is not a text file", file.filename))?;
+        let (imported, mut errors) = export::import_csv(&matrix.core, &text);
+        for row in imported.iter().filter(|x| matrix.is_disputed(x.player, x.opponent)){
+            errors.push(format!("line {}: the match between {} and {} is disputed", row.line, matrix.core.name_of(row.player), matrix.core.name_of(row.opponent)));
+        }
+        if !errors.is_empty() {
+            //Keep the list within a single message
+            let mut output = format!("Nothing was imported, {} has {} problems:", file.filename, errors.len());
+            for (shown, error) in errors.iter().enumerate(){
+                if output.len() + error.len() > 1800 {
+                    output += &format!("\n...and {} more", errors.len() - shown);
+                    break
+                }
+                output += &format!("\n{}", error);
+            }
+            return Err(anyhow!(output));
+        }
+        if imported.is_empty() {
+            return Err(anyhow!("no results found in {}", file.filename));
+        }
+
+        //Every row is valid, so record them all before touching Discord
+        let mut superseded = Vec::new();
+        let mut changed = 0;
+        for row in &imported{
+            let old_result = matrix.core.set_result(row.player, row.opponent, row.result.clone())?;
+            if old_result != row.result {
+                changed += 1;
+            }
+            superseded.extend(matrix.take_pending(row.player, row.opponent));
+            matrix.history.push(ReportEvent{reporter: command.user.id, player: row.player, opponent: row.opponent, old_result,
+                new_result: row.result.clone(), timestamp: Timestamp::now(), interaction: command.id, undo_of: None, note: None});
+        }
+        for report in superseded{
+            Self::close_pending(discord, matrix, &report).await?;
+        }
+        discord.say(matrix.thread, format!("{} imported {} results from {}, {} of them changed", command.user, imported.len(), file.filename, changed)).await?;
+        Self::update_grid(discord, matrix).await?;
+        Ok(format!("Imported {} results into {}", imported.len(), matrix.threadname))
+    }
+
+    async fn history(&self, command: &CommandInteraction) -> Result<String>{
+        let options = &command.data.options();
+        let guild = command.guild_id.context("guild not found for history")?;
+        let Some(match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
+        let match_data_list = match_data_list.get();
+
+        let Some(ResolvedOption {
+            value: ResolvedValue::String(commandshortname), ..
+        }) = options.get(0) else {return Err(anyhow!("name not found in history"));};
+        let find_user = |name| match find_option(options, name) {
+            Some(ResolvedValue::User(user, _)) => Some(player_id(user.id)),
+            _ => None
+        };
+        let player = find_user("player");
+        let opponent = find_user("opponent");
+        if opponent.is_some() && player.is_none() {
+            return Err(anyhow!("an opponent can only be given along with a player"));
+        }
+
+        let matchup = match_data_list.get(*commandshortname).context("unable to find given name in match list")?;
+        let name_of = |id| matchup.core.name_of(id);
+        let mut lines = Vec::new();
+        for event in &matchup.history{
+            let (event_player, event_opponent, old_result, new_result) = match player {
+                //Show the events from the requested player's side
+                Some(player) if event.opponent == player => (event.opponent, event.player, event.old_result.invert(), event.new_result.invert()),
+                _ => (event.player, event.opponent, event.old_result.clone(), event.new_result.clone()),
+            };
+            if player.is_some_and(|x| x != event_player) || opponent.is_some_and(|x| x != event_opponent) { continue }
+            let mut line = format!("<t:{}:f> <@{}>: {} {} {}", event.timestamp.unix_timestamp(), event.reporter, name_of(event_player), new_result.to_str(), name_of(event_opponent));
+            if event.undo_of.is_some() {
+                line += " (undo)";
+            } else if old_result != MatchResult::NotPlayed {
+                line += &format!(" (was {})", old_result.to_str());
+            }
+            if let Some(note) = &event.note {
+                line += &format!(" - ruling: {}", note);
+            }
+            lines.push(line);
+        }
+        if lines.is_empty() {
+            return Ok("No reports found".to_string());
+        }
+        //Keep the most recent reports that fit in a single message
+        let mut output = String::new();
+        let mut shown = 0;
+        for line in lines.iter().rev(){
+            if output.len() + line.len() > 1800 { break }
+            output = format!("{}\n{}", line, output);
+            shown += 1;
+        }
+        if shown < lines.len() {
+            output = format!("{} earlier reports not shown\n{}", lines.len() - shown, output);
+        }
+        Ok(output)
+    }
+
+    async fn ping(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
         let options = &command.data.options();
         let guild = command.guild_id.context("guild not found for ping")?;
         let Some(match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
@@ -413,11 +1952,11 @@ impl Handler{
 
         let matchup = match_data_list.get(*commandshortname).context("unable to find given name in match list")?;
         let mut message_str = String::new();
-        for user in &matchup.users{
+        for user in &matchup.core.users{
             message_str = message_str+"<@"+&user.id.to_string()+"> ";
         }
-        command.channel_id.send_message(&ctx.http, CreateMessage::new()
-                .allowed_mentions(CreateAllowedMentions::new().users(matchup.users.iter().map(|x| &x.user)))
+        discord.send_message(command.channel_id, CreateMessage::new()
+                .allowed_mentions(CreateAllowedMentions::new().users(matchup.core.users.iter().map(|x| user_id(x.id))))
                 .flags(MessageFlags::SUPPRESS_NOTIFICATIONS)
                 .content(message_str))
                 .await?;
@@ -425,41 +1964,28 @@ impl Handler{
     }
 
 
-    async fn fam_pings(&self, ctx: &Context, command: &CommandInteraction) -> Result<String>{
+    async fn fam_pings(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
         let options = &command.data.options();
         let guild = command.guild_id.context("guild not found for ping")?;
         let Some(match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
         let match_data_list = match_data_list.get();
-        let playerid = command.user.id;
+        let playerid = player_id(command.user.id);
         let mut mentions = HashSet::new();
 
 
-        #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-        enum RestrictValues {
-            NoRestriction, ExcludeDangerous, ExcludeNormal
-        }
-        fn get_opponents(playerid: UserId, matrix: &MatchMatrix, mentions: &mut HashSet<UserId>, restrict: RestrictValues, text_only: bool) -> Option<String> {
-            let _ = lookup_userid(playerid, &matrix.users)?; // Confirm the user is in the matrix
+        fn get_opponents(playerid: PlayerId, matrix: &Matrix, mentions: &mut HashSet<UserId>, exclude: Option<&BTreeSet<PlayerId>>, include: Option<&BTreeSet<PlayerId>>, text_only: bool) -> Option<String> {
+            let _ = matrix.player(playerid)?; // Confirm the user is in the matrix
+            if matrix.withdrawn.contains(&playerid) {return Some(String::from("withdrawn"));}
             let mut message_str = String::new();
             let mut found_any = false;
-            const DANGEROUS_LIST: [UserId; 4] =[
-                UserId::new(183433751689166850), //notgreat
-                UserId::new(1165425089676849182), //inuenc
-                UserId::new(643842082435235862), //Nibiru
-                UserId::new(249299939522248704)]; //coopergfrye 
-            for opponent in &matrix.users{
-                let restricted_result = match restrict {
-                    RestrictValues::NoRestriction => false,
-                    RestrictValues::ExcludeDangerous => DANGEROUS_LIST.contains(&opponent.id),
-                    RestrictValues::ExcludeNormal => !DANGEROUS_LIST.contains(&opponent.id),
-                };
-                //Self is MatchResult::Unplayable so no need to special case it
-                if matrix.results.get(&(playerid, opponent.id)) == Some(&MatchResult::NotPlayed) && !restricted_result{
+            for opponent in matrix.unplayed_opponents(playerid){
+                let restricted_result = exclude.is_some_and(|x| x.contains(&opponent.id)) || include.is_some_and(|x| !x.contains(&opponent.id));
+                if !restricted_result{
                     if matrix.disabled_fam.contains(&opponent.id) || text_only {
                         message_str += &format!("{} ", opponent.name);
                     } else {
                         message_str += &format!("<@{}> ", opponent.id);
-                        mentions.insert(opponent.id);
+                        mentions.insert(user_id(opponent.id));
                     }
                     found_any = true;
                 }
@@ -469,46 +1995,36 @@ impl Handler{
         }
 
         let mut output = format!("<@{}> is trying to find a match to play, is anyone available?", playerid);
-        let commandshortname = match options.get(0){
-            Some(ResolvedOption {
-                value: ResolvedValue::String(commandshortname), ..
-            }) => commandshortname,
+        let commandshortname = match find_option(options, "tournament"){
+            Some(ResolvedValue::String(commandshortname)) => commandshortname,
             None => "",
             _ => return Err(anyhow!("Bad command arguments"))
         };
-        let text_only = match options.get(1){
-            Some(ResolvedOption {
-                value: ResolvedValue::Boolean(bool_val), ..
-            }) => *bool_val,
+        let text_only = match find_option(options, "text-only"){
+            Some(ResolvedValue::Boolean(bool_val)) => *bool_val,
             None => false,
             _ => return Err(anyhow!("Bad command arguments"))
         };
-        let restrict = match options.get(2){
-            Some(ResolvedOption {
-                value: ResolvedValue::Integer(restrict_str), ..
-            }) => *restrict_str,
-            None => 0,
-            _ => return Err(anyhow!("Bad command arguments"))
-        };
-        let restrict = match restrict {
-            0 => RestrictValues::NoRestriction,
-            1 => RestrictValues::ExcludeDangerous,
-            2 => RestrictValues::ExcludeNormal,
-            _=> return Err(anyhow!("Unexpected restrict value"))
+        let config = self.config_data.get_async(&guild).await;
+        let group = |name| match find_option(options, name){
+            Some(ResolvedValue::String(group)) => config.as_ref().context("this server has no player groups")?.get().group(group).map(Some),
+            _ => Ok(None),
         };
+        let exclude = group("exclude")?;
+        let include = group("include")?;
 
         for (shortname, matrix) in match_data_list.iter(){
-            if commandshortname.is_empty() && matrix.disabled_fam.contains(&playerid) {
+            if commandshortname.is_empty() && matrix.core.disabled_fam.contains(&playerid) {
                 output += &format!("\n{}: muted", shortname);
                 continue;
             }
             if commandshortname.is_empty() || commandshortname == shortname{
-                if let Some(opponents_string) = get_opponents(playerid, matrix, &mut mentions, restrict, text_only){
+                if let Some(opponents_string) = get_opponents(playerid, &matrix.core, &mut mentions, exclude, include, text_only){
                     output += &format!("\n{}: {}", shortname, &opponents_string);
                 }
             }
         }
-        command.channel_id.send_message(&ctx.http, CreateMessage::new()
+        discord.send_message(command.channel_id, CreateMessage::new()
                 .allowed_mentions(CreateAllowedMentions::new().users(mentions.iter()))
                 .content(output))
                 .await?;
@@ -516,12 +2032,85 @@ impl Handler{
         Ok("Success".to_string())
     }
 
-    async fn findable(&self, ctx: &Context, command: &CommandInteraction) -> Result<String>{
+    async fn player_groups(&self, command: &CommandInteraction) -> Result<String>{
+        let options = &command.data.options();
+        let guild = command.guild_id.context("guild not found for group")?;
+        let Some(ResolvedOption {
+            name: action, value: ResolvedValue::SubCommand(suboptions), ..
+        }) = options.first() else {return Err(anyhow!("subcommand not found in group"));};
+        let mut config = self.config_data.entry_async(guild).await.or_default();
+        let groups = &mut config.get_mut().groups;
+        if *action == "list" {
+            if groups.is_empty() {
+                return Ok("No player groups yet, make one with /group add".to_string());
+            }
+            return Ok(groups.iter().map(|(name, players)| format!("{}: {}", name,
+                players.iter().map(|x| format!("<@{}>", x)).collect::<Vec<_>>().join(" "))).collect::<Vec<_>>().join("\n"));
+        }
+
+        let Some(ResolvedValue::String(name)) = find_option(suboptions, "name") else {return Err(anyhow!("name not found in group"));};
+        let Some(ResolvedValue::User(user, _)) = find_option(suboptions, "player") else {return Err(anyhow!("player not found in group"));};
+        let name = name.trim().to_lowercase();
+        let player = player_id(user.id);
+        match *action {
+            "add" => {
+                if name.is_empty() {
+                    return Err(anyhow!("Group names can't be blank"));
+                }
+                if !groups.entry(name.clone()).or_default().insert(player) {
+                    return Err(anyhow!("<@{}> is already in {}", player, name));
+                }
+                Ok(format!("Added <@{}> to {}", player, name))
+            },
+            "remove" => {
+                let group = groups.get_mut(&name).with_context(|| format!("no player group named {}", name))?;
+                if !group.remove(&player) {
+                    return Err(anyhow!("<@{}> isn't in {}", player, name));
+                }
+                //Groups only exist while they have players
+                if group.is_empty() {
+                    groups.remove(&name);
+                    return Ok(format!("Removed <@{}>, {} is now empty and deleted", player, name));
+                }
+                Ok(format!("Removed <@{}> from {}", player, name))
+            },
+            _ => Err(anyhow!("Unknown group action {}", action)),
+        }
+    }
+
+    async fn set_theme(&self, command: &CommandInteraction) -> Result<String>{
+        let options = &command.data.options();
+        let guild = command.guild_id.context("guild not found for theme")?;
+        let mut config = self.config_data.entry_async(guild).await.or_default();
+        let config = config.get_mut();
+        //Start from the preset if one is given, otherwise change the current theme
+        let mut theme = match find_option(options, "preset") {
+            Some(ResolvedValue::String(name)) => Theme::preset(name).context("unknown theme")?,
+            _ => config.theme.clone().unwrap_or_default(),
+        };
+        if let Some(ResolvedValue::String(list)) = find_option(options, "wins") {
+            theme.wins = Theme::parse_rungs(list).context("wins")?;
+        }
+        if let Some(ResolvedValue::String(list)) = find_option(options, "losses") {
+            theme.losses = Theme::parse_rungs(list).context("losses")?;
+        }
+        for (name, icon) in [("draw", &mut theme.draw), ("available", &mut theme.available), ("unplayable", &mut theme.unplayable), ("forfeit", &mut theme.forfeit)]{
+            if let Some(ResolvedValue::String(value)) = find_option(options, name) {
+                *icon = value.trim().to_string();
+            }
+        }
+        theme.validate()?;
+        let legend = theme.legend(MatchFormat{first_to: 4, draws: true});
+        config.theme = Some(theme);
+        Ok(format!("New tournaments will use these icons:\n{}", legend))
+    }
+
+    async fn findable(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
         let options = &command.data.options();
         let guild = command.guild_id.context("guild not found for ping")?;
         let Some(mut match_data_list) = self.match_data.get_async(&guild).await else { return Err(anyhow!("guild has no match matrices"));};
         let match_data_list = match_data_list.get_mut();
-        let playerid = command.user.id;
+        let playerid = player_id(command.user.id);
 
         let Some(ResolvedOption {
             value: ResolvedValue::String(commandshortname), ..
@@ -533,84 +2122,105 @@ impl Handler{
 
         let mut count:i32 = 0;
         for (shortname, matrix) in match_data_list.iter_mut(){
-            if (commandshortname.is_empty() || commandshortname == shortname) && lookup_userid(playerid, &matrix.users).is_some(){
-                let result = if enable {matrix.disabled_fam.remove(&playerid)} else {matrix.disabled_fam.insert(playerid)};
+            if (commandshortname.is_empty() || commandshortname == shortname) && matrix.core.player(playerid).is_some(){
+                let result = if enable {matrix.core.disabled_fam.remove(&playerid)} else {matrix.core.disabled_fam.insert(playerid)};
                 if result {
                     count += 1;
-                    let messages = render_grid(&matrix.users, &matrix.results, &matrix.disabled_fam, &matrix.threadname, matrix.mainposts.len())?;
-                    for (msg, post) in messages.iter().zip(&matrix.mainposts){
-                        matrix.thread.message(&ctx.http, post).await?.edit(&ctx.http, EditMessage::new().content(msg)).await?;
-                    }
+                    Self::update_grid(discord, matrix).await?;
                 }
             }
         }
         Ok(format!("Success - {} findable statuses changed", count))
     }
 
-    async fn reprocess(&self, ctx: &Context, command: &CommandInteraction) -> Result<String>{
+    async fn reprocess(&self, discord: &dyn Discord, command: &CommandInteraction) -> Result<String>{
         let guild = command.guild_id.context("guild not found for reprocess")?;
-        let messages = command.channel_id.messages(ctx, GetMessages::new().after(1)).await?;
+        let messages = discord.messages(command.channel_id).await?;
         let intro = &messages.get(messages.len()-1).context("intro message not found")?.content;
 
         //Read intro post for users and command name
-        static RE_INTRO: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(.*) Report your results here using the command /([^ ]+) or /result").unwrap());
-        let content_match = RE_INTRO.captures(intro).context("intro message does not match expected")?;
-        let user_str_list = content_match[1].split(" ");
+        let intro = Intro::parse(intro)?;
+        if intro.swiss_rounds.is_some() {
+            return Err(anyhow!("Swiss pairings can't be read back from the thread"));
+        }
         let mut user_list = Vec::new();
-        let mut disabled_fam = HashSet::new();
-        for str in user_str_list{
-            static RE_USERID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<@(\d+)>").unwrap());
-            if let Some(user_match) = RE_USERID.captures(str){
-                let user = UserId::new(user_match[1].parse()?);
-                user_list.push(member_to_user(&guild.member(&ctx.http, user).await?));
-            }
+        for user in &intro.players{
+            user_list.push(Player{name: discord.member_name(guild, user_id(*user)).await?, id: *user});
         }
-        let shortname = &content_match[2];
+        let shortname = intro.shortname.as_str();
+
+        //The grid is read back in the theme it was posted with, which older intros don't record
+        let previous_theme = self.match_data.read_async(&guild, |_, x| x.get(shortname).filter(|x| x.thread == command.channel_id).map(|x| x.theme.clone())).await.flatten();
+        let theme = match previous_theme.or(intro.theme.clone()) {
+            Some(theme) => theme,
+            None => self.guild_theme(guild).await,
+        };
 
-        //Read the matrix results
-        let mut results = HashMap::new();
+        //Read the matrix results, which start with the thread name and follow the intro unless players were added since
+        let fullname = command.channel.as_ref().context("getting channel/thread")?.name.as_ref().context("getting channel/thread name")?;
         let mut mainposts = Vec::new();
-        let mut message_offset = 2;
+        let mut message_offset = messages.iter().position(|x| x.author_bot && x.content.starts_with(&format!("{}\n", fullname))).map_or(2, |x| messages.len()-x);
         let mut total_matrix = String::new();
-        static RE_MATCH_ICONS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r":cloud:|:full_moon:|:waning_gibbous_moon:|:waxing_crescent_moon:|:new_moon:|:black_small_square:").unwrap());
-        loop {
-            let Some(matrix_post) = &messages.get(messages.len()-message_offset) else { break };
-            if !matrix_post.author.bot { break }
-            if !matrix_post.content.contains(":") { break }
+        //The legend can be the thread's last post, so the offset may run past its start
+        while let Some(matrix_post) = messages.len().checked_sub(message_offset).and_then(|x| messages.get(x)) {
+            if !matrix_post.author_bot { break }
+            //Unicode emoji themes have no colons
+            if !matrix_post.content.contains(":") && grid::count_icons(&matrix_post.content, &theme) == 0 { break }
             mainposts.push(matrix_post.id);
             total_matrix.push_str(&matrix_post.content);
             message_offset += 1;
         }
-        let mut matrix_match = RE_MATCH_ICONS.find_iter(&total_matrix);
-        for y in &user_list{
-            for x in &user_list{
-                let result = MatchResult::get(matrix_match.next()
-                    .context(format!("Unable to find match results matrix content for {},{}", x.name, y.name))?.as_str());
-                results.insert((x.id, y.id), result);
-            }
-            if total_matrix.contains(&format!("{}:no_bell:", y.name)) {
-                disabled_fam.insert(y.id);
-            }
-        }
-        let count = matrix_match.count();
-        if count == 6 {
-            mainposts.pop(); // Remove the explanation post, the expected situation
+        //A grid image is a bot post with only the file, straight after the legend
+        let found_image = messages.len().checked_sub(message_offset).and_then(|x| messages.get(x)).filter(|x| x.author_bot && x.content.is_empty()).map(|x| x.id);
+        let mut parsed = grid::parse_grid(&total_matrix, &user_list, intro.format, &theme)?;
+        let count = parsed.extra_icons;
+        let legend = grid::legend(&theme, intro.format, false, intro.double, intro.confirm);
+        let legend_count = grid::count_icons(&legend, &theme);
+        let legend_post = if count == legend_count {
+            mainposts.pop() // Remove the explanation post, the expected situation
+        } else if count+1 == legend_count { // Legends posted before forfeits were explained, bring it up to date
+            let id = mainposts.pop().context("Something is very broken")?;
+            discord.edit_message(command.channel_id, id, EditMessage::new().content(legend)).await?;
+            Some(id)
         } else if count == 0 && mainposts.len() >= 2 { // No explanation post, add it back in place of last bot post
             let id = mainposts.pop().context("Something is very broken")?;
-            let msg = ":cloud: match available\n:full_moon: match won 2-0\n:waning_gibbous_moon: match won 2-1\n\
-                :waxing_crescent_moon: match lost 1-2\n:new_moon: match lost 0-2\n:black_small_square: cannot play yourself";
-            command.channel_id.message(&ctx.http, id).await?.edit(&ctx.http, EditMessage::new().content(msg)).await?;
+            discord.edit_message(command.channel_id, id, EditMessage::new().content(legend)).await?;
+            Some(id)
         } else  {
-            return Err(anyhow!("Symbol count in match matrix did not match expected: {} excess symbols found but expected 6", count));
-        }
+            return Err(anyhow!("Symbol count in match matrix did not match expected: {} excess symbols found but expected {}", count, legend_count));
+        };
 
         //final setup
         let user_count = user_list.len();
-        let fullname = command.channel.as_ref().context("getting channel/thread")?.name.as_ref().context("getting channel/thread name")?;
-        let matrix = MatchMatrix{thread: command.channel_id, threadname:fullname.to_string(), mainposts, users: user_list, results, disabled_fam};
         let mut match_vec = self.match_data.entry_async(guild).await.or_insert(HashMap::new());
+        //Keep the report history and settings if this thread was already being tracked
+        let previous = match_vec.get().get(shortname).filter(|x| x.thread == command.channel_id);
+        let history = previous.map(|x| x.history.clone()).unwrap_or_default();
+        let tiebreakers = previous.map_or_else(standings::default_tiebreakers, |x| x.core.tiebreakers.clone());
+        let confirm_timeout = previous.and_then(|x| x.confirm_timeout);
+        let pending = previous.map(|x| x.pending.clone()).unwrap_or_default();
+        let disputes = previous.map(|x| x.disputes.clone()).unwrap_or_default();
+        let organizer = previous.map_or(Some(command.user.id), |x| x.organizer);
+        let policy = previous.map(|x| x.policy).unwrap_or_default();
+        let withdrawals = previous.map(|x| x.withdrawals).unwrap_or_default();
+        let deadline = previous.and_then(|x| x.deadline);
+        let reminders = previous.map(|x| x.reminders.clone()).unwrap_or_default();
+        let reminders_sent = previous.map(|x| x.reminders_sent.clone()).unwrap_or_default();
+        let audit_channel = previous.and_then(|x| x.audit_channel);
+        let image_post = previous.and_then(|x| x.image_post).or(found_image);
+        //Pending and disputed squares hide the recorded result
+        for key in parsed.hidden{
+            if let Some(result) = previous.and_then(|x| x.core.results.get(&key)) {
+                parsed.results.insert(key, result.clone());
+            }
+        }
+        let core = Matrix{users: user_list, results: parsed.results, disabled_fam: parsed.disabled_fam, format: intro.format, tiebreakers, swiss: None, double: intro.double,
+            withdrawn: parsed.withdrawn};
+        let matrix = MatchMatrix{thread: command.channel_id, threadname:fullname.to_string(), mainposts, core, history,
+            confirm: intro.confirm, confirm_timeout, pending, disputes, organizer, policy, audit_channel, theme, legend_post, image_post, withdrawals,
+            deadline, reminders, reminders_sent};
         match_vec.get_mut().insert(shortname.to_string(), matrix);
-        Self::reset_tournament_commands(ctx, &guild, &match_vec).await?;
+        self.reset_commands(discord, &guild, &match_vec).await?;
         
         Ok(format!("Processed {} ({}) with {} users - currently running {} tournaments", fullname, shortname, user_count, match_vec.len()))
     }
@@ -628,6 +2238,7 @@ impl EventHandler for Handler {
         }
     }*/
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        let discord: &dyn Discord = &*ctx.http;
         if let Interaction::Command(command) = interaction {
             let response1 = command.create_response(&ctx.http, CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().ephemeral(true)
                 .content("Processing"))).await;
@@ -638,16 +2249,32 @@ impl EventHandler for Handler {
             //println!("Received command interaction: {command:#?}");
             let result = match command.data.name.as_str() {
                 "begin" => self.begin(&command).await,
-                "add" => self.add_users(&ctx, &command).await,
-                "create" => self.create(&ctx, &command).await,
-                "cancel" => self.cancel(&command).await,
-                "end" => self.end(&ctx, &command).await,
-                "result" => self.report_result_any(&ctx, &command).await,
-                "reprocess" => self.reprocess(&ctx, &command).await,
-                "ping" => self.ping(&ctx, &command).await,
-                "fam" => self.fam_pings(&ctx, &command).await,
-                "matchpings" => self.findable(&ctx, &command).await,
-                _ => self.report_result_command(&ctx, &command).await,
+                "add" => self.add_users(discord, &command).await,
+                "create" => self.create(discord, &command).await,
+                "cancel" => self.cancel(discord, &command).await,
+                "signup" => self.signup(discord, &command).await,
+                "end" => self.end(discord, &command).await,
+                "result" => self.report_result_any(discord, &command).await,
+                "reprocess" => self.reprocess(discord, &command).await,
+                "ping" => self.ping(discord, &command).await,
+                "fam" => self.fam_pings(discord, &command).await,
+                "matchpings" => self.findable(discord, &command).await,
+                "group" => self.player_groups(&command).await,
+                "theme" => self.set_theme(&command).await,
+                "history" => self.history(&command).await,
+                "standings" => self.standings(discord, &command).await,
+                "export" => self.export(discord, &command).await,
+                "import" => self.import(discord, &command).await,
+                "addplayer" => self.change_players(discord, &command, true).await,
+                "removeplayer" => self.change_players(discord, &command, false).await,
+                "withdraw" => self.withdraw(discord, &command).await,
+                "deadline" => self.set_deadline(discord, &command).await,
+                "nextround" => self.next_round(discord, &command).await,
+                "playoffs" => self.playoffs(discord, &command).await,
+                "undo" => self.undo_command(discord, &command).await,
+                "dispute" => self.dispute(discord, &command).await,
+                "resolve" => self.resolve(discord, &command).await,
+                _ => self.report_result_command(discord, &command).await,
             };
 
             let response2 = command.edit_response(&ctx.http, EditInteractionResponse::new().content(
@@ -661,19 +2288,116 @@ impl EventHandler for Handler {
             if let Err(why) = response2{
                 println!("Cannot edit slash command response: {why}");
             }
+            if let Some(guild) = command.guild_id{
+                if let Err(why) = self.persist(guild).await{
+                    println!("Error saving guild {guild}: {why}");
+                }
+            }
+        } else if let Interaction::Autocomplete(autocomplete) = interaction {
+            let response = match autocomplete.data.name.as_str() {
+                "fam" | "group" => self.autocomplete_group(&autocomplete).await,
+                "add" | "create" | "cancel" | "signup" => self.autocomplete_draft(&autocomplete).await,
+                _ => self.autocomplete_opponent(&autocomplete).await,
+            };
+            let response = match response {
+                Ok(response) => response,
+                Err(why) => {
+                    println!("Error autocompleting {}: {}", autocomplete.data.name.as_str(), why);
+                    return;
+                },
+            };
+            if let Err(why) = autocomplete.create_response(&ctx.http, CreateInteractionResponse::Autocomplete(response)).await{
+                println!("Cannot respond to autocomplete: {why}");
+            }
+        } else if let Interaction::Component(component) = interaction {
+            let response1 = component.create_response(&ctx.http, CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().ephemeral(true)
+                .content("Processing"))).await;
+            if let Err(why) = response1{
+                println!("Cannot respond to component: {why}");
+                return;
+            }
+            let result = match component.data.custom_id.split_once(':') {
+                Some(("undo", event_index)) => self.undo_button(discord, &component, event_index).await,
+                Some(("confirm", interaction)) => self.confirm_button(discord, &component, interaction, true).await,
+                Some(("reject", interaction)) => self.confirm_button(discord, &component, interaction, false).await,
+                Some(("signup", action)) => self.signup_button(discord, &component, action).await,
+                _ => Err(anyhow!("Unknown button")),
+            };
+
+            let response2 = component.edit_response(&ctx.http, EditInteractionResponse::new().content(
+                match result{
+                    Err(why) => {
+                        println!("Error processing {}: {}", component.data.custom_id, why);
+                        why.to_string()
+                    },
+                    Ok(success_result) => success_result,
+                })).await;
+            if let Err(why) = response2{
+                println!("Cannot edit component response: {why}");
+            }
+            if let Some(guild) = component.guild_id{
+                if let Err(why) = self.persist(guild).await{
+                    println!("Error saving guild {guild}: {why}");
+                }
+            }
         }
     }
 
     async fn ready(&self, ctx: Context, _ready: Ready) {
+        if let Err(why) = self.restore(&*ctx.http).await{
+            println!("Error restoring saved tournaments: {why}");
+        }
+        //ready fires again on reconnects, only start one sweeper
+        if !self.sweeper_started.swap(true, Ordering::SeqCst) {
+            let handler = self.clone();
+            let http = ctx.http.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(SWEEP_INTERVAL).await;
+                    if let Err(why) = handler.expire_pending(&*http).await{
+                        println!("Error timing out pending reports: {why}");
+                    }
+                    if let Err(why) = handler.send_reminders(&*http).await{
+                        println!("Error sending deadline reminders: {why}");
+                    }
+                    if let Err(why) = handler.close_signups(&*http).await{
+                        println!("Error closing sign-ups: {why}");
+                    }
+                }
+            });
+        }
         //Only need to do this once (or if I change the commands)
         /*let result = Command::set_global_commands(&ctx.http, vec![
             CreateCommand::new("begin").description("Begin setting up a new match matrix")
                 .default_member_permissions(Permissions::MODERATE_MEMBERS)
                 .add_option(CreateCommandOption::new(CommandOptionType::String, "title", "The name of the thread to make").required(true))
-                .add_option(CreateCommandOption::new(CommandOptionType::String, "cmd", "The new command-name for results (lower case, no spaces)").required(true)),
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "cmd", "The new command-name for results (lower case, no spaces)").required(true))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "format", "How many games each match is (default best of 3)")
+                    .add_string_choice("Best of 1", "bo1").add_string_choice("Best of 3", "bo3")
+                    .add_string_choice("Best of 5", "bo5").add_string_choice("Best of 7", "bo7"))
+                .add_option(CreateCommandOption::new(CommandOptionType::Integer, "first-to", "Alternatively, how many game wins end a match")
+                    .min_int_value(1).max_int_value(MatchFormat::MAX_FIRST_TO as u64))
+                .add_option(CreateCommandOption::new(CommandOptionType::Boolean, "draws", "Can matches end in a draw (default no)"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "tiebreakers", "Order of h2h, games, sb, sos separated by commas (default all in that order)"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "mode", "Play everyone (default) or Swiss rounds paired by score")
+                    .add_string_choice("Round-robin", "round-robin").add_string_choice("Swiss", "swiss"))
+                .add_option(CreateCommandOption::new(CommandOptionType::Integer, "rounds", "Number of Swiss rounds (default enough for one undefeated player)").min_int_value(1))
+                .add_option(CreateCommandOption::new(CommandOptionType::Boolean, "double", "Every pair plays twice, home and away (default no)"))
+                .add_option(CreateCommandOption::new(CommandOptionType::Boolean, "confirm", "Player reports wait for the opponent to confirm them (default no)"))
+                .add_option(CreateCommandOption::new(CommandOptionType::Integer, "confirm-timeout", "Minutes after which unconfirmed reports count anyway (default never)").min_int_value(1))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "reporting", "Who can report results (default the players or an organizer)")
+                    .add_string_choice("Players only", "participants").add_string_choice("Players or organizers", "participants-or-organizers")
+                    .add_string_choice("Anyone", "anyone"))
+                .add_option(CreateCommandOption::new(CommandOptionType::Channel, "audit-channel", "Channel to log rejected report attempts in")
+                    .channel_types(vec![ChannelType::Text]))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "theme", "Emoji for the grid (default the server's theme, set with /theme)")
+                    .add_string_choice("Moon", "moon").add_string_choice("Circles", "circles").add_string_choice("Hearts", "hearts"))
+                .add_option(CreateCommandOption::new(CommandOptionType::Boolean, "image", "Also post the grid as a picture, easier to read on phones (default no)"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "withdrawals", "What happens to a withdrawn player's matches (default void)")
+                    .add_string_choice("Void all of them", "void").add_string_choice("Forfeit the unplayed ones", "forfeit")),
             CreateCommand::new("add").description("Add user(s) for setup")
                 .default_member_permissions(Permissions::MODERATE_MEMBERS)
-                .add_option(CreateCommandOption::new(CommandOptionType::User, "player", "First user to add").required(true))
+                .add_option(CreateCommandOption::new(CommandOptionType::User, "player", "First user to add"))
                 .add_option(CreateCommandOption::new(CommandOptionType::User, "player2", "Second user"))
                 .add_option(CreateCommandOption::new(CommandOptionType::User, "player3", "Third user"))
                 .add_option(CreateCommandOption::new(CommandOptionType::User, "player4", "Fourth user"))
@@ -682,20 +2406,63 @@ impl EventHandler for Handler {
                 .add_option(CreateCommandOption::new(CommandOptionType::User, "player7", "Seventh user"))
                 .add_option(CreateCommandOption::new(CommandOptionType::User, "player8", "Eighth user"))
                 .add_option(CreateCommandOption::new(CommandOptionType::User, "player9", "Ninth user"))
-                .add_option(CreateCommandOption::new(CommandOptionType::User, "player10", "Tenth user (if you need more, call the command again)")),
+                .add_option(CreateCommandOption::new(CommandOptionType::User, "player10", "Tenth user (for more use a role or reactions)"))
+                .add_option(CreateCommandOption::new(CommandOptionType::Role, "role", "Add everyone with this role"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "reactions", "Add everyone who reacted to this sign-up message (link or id)"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "draft", "Which setup, by its cmd (default the one you began)").set_autocomplete(true)),
             CreateCommand::new("create").description("Create the match results matrix thread in this channel")
-                .default_member_permissions(Permissions::MODERATE_MEMBERS),
+                .default_member_permissions(Permissions::MODERATE_MEMBERS)
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "draft", "Which setup, by its cmd (default the one you began)").set_autocomplete(true)),
             CreateCommand::new("cancel").description("Cancel the current match matrix setup")
-                .default_member_permissions(Permissions::MODERATE_MEMBERS),
+                .default_member_permissions(Permissions::MODERATE_MEMBERS)
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "draft", "Which setup, by its cmd (default the one you began)").set_autocomplete(true)),
+            CreateCommand::new("signup").description("Post a message players can join the current setup from")
+                .default_member_permissions(Permissions::MODERATE_MEMBERS)
+                .add_option(CreateCommandOption::new(CommandOptionType::Integer, "cap", "Most players that can join, later ones go on a waitlist (default no limit)").min_int_value(2))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "closes", "UTC date like 2025-06-30, or 2025-06-30 18:00, to close the sign-up (default at /create)"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "draft", "Which setup, by its cmd (default the one you began)").set_autocomplete(true)),
             CreateCommand::new("reprocess").description("Read this channel's matrix info into storage. Also resets unavailable report commands")
                 .default_member_permissions(Permissions::MODERATE_MEMBERS),
             CreateCommand::new("result").description("Report a match result with arbitrary users for the current results thread")
-                .add_option(CreateCommandOption::new(CommandOptionType::String, "score", "What was the match score")
-                    .add_string_choice("2-0 (Win)", "2-0").add_string_choice("2-1 (Win)", "2-1")
-                    .add_string_choice("1-2 (Loss)", "1-2").add_string_choice("0-2 (Loss)", "0-2")
-                    .add_string_choice("0-0 (No result)", "0-0").required(true))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "score", "What was the match score, e.g. 2-1 (first player first, 0-0 for no result)").required(true))
                 .add_option(CreateCommandOption::new(CommandOptionType::User, "opponent", "The second player in the match").required(true))
-                .add_option(CreateCommandOption::new(CommandOptionType::User, "player", "Use an alternative first player in the match (otherwise assumed to be you)")),
+                .add_option(CreateCommandOption::new(CommandOptionType::User, "player", "Use an alternative first player in the match (otherwise assumed to be you)"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "leg", "Home and away tournaments: the first player's home or away match")
+                    .add_string_choice("Home", "home").add_string_choice("Away", "away")),
+            CreateCommand::new("undo").description("Undo your latest result report in this results thread (moderators: the latest report overall)"),
+            CreateCommand::new("dispute").description("Flag a match in this results thread as disputed, for an organizer to resolve")
+                .add_option(CreateCommandOption::new(CommandOptionType::User, "opponent", "The second player in the match").required(true))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "reason", "What is wrong with the reported result"))
+                .add_option(CreateCommandOption::new(CommandOptionType::User, "player", "Use an alternative first player in the match (otherwise assumed to be you)"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "leg", "Home and away tournaments: the first player's home or away match")
+                    .add_string_choice("Home", "home").add_string_choice("Away", "away")),
+            CreateCommand::new("resolve").description("Settle a disputed match in this results thread with a final result")
+                .default_member_permissions(Permissions::MODERATE_MEMBERS)
+                .add_option(CreateCommandOption::new(CommandOptionType::User, "player", "The first player in the match").required(true))
+                .add_option(CreateCommandOption::new(CommandOptionType::User, "opponent", "The second player in the match").required(true))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "score", "The final score, e.g. 2-1 (first player first, 0-0 for no result)").required(true))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "note", "The reason for the ruling, kept in the history").required(true))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "leg", "Home and away tournaments: the first player's home or away match")
+                    .add_string_choice("Home", "home").add_string_choice("Away", "away")),
+            CreateCommand::new("group").description("Manage the named player groups that /fam can include or exclude")
+                .default_member_permissions(Permissions::MODERATE_MEMBERS)
+                .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "add", "Add a player to a group, creating it if needed")
+                    .add_sub_option(CreateCommandOption::new(CommandOptionType::String, "name", "The group, e.g. strong").required(true).set_autocomplete(true))
+                    .add_sub_option(CreateCommandOption::new(CommandOptionType::User, "player", "The player to add").required(true)))
+                .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "remove", "Remove a player from a group, deleting it once empty")
+                    .add_sub_option(CreateCommandOption::new(CommandOptionType::String, "name", "The group").required(true).set_autocomplete(true))
+                    .add_sub_option(CreateCommandOption::new(CommandOptionType::User, "player", "The player to remove").required(true)))
+                .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "list", "List every group and its players")),
+            CreateCommand::new("theme").description("Choose the emoji that new tournaments' grids use in this server")
+                .default_member_permissions(Permissions::MODERATE_MEMBERS)
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "preset", "Start from a built-in theme (default the current one)")
+                    .add_string_choice("Moon", "moon").add_string_choice("Circles", "circles").add_string_choice("Hearts", "hearts"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "wins", "4 emoji for wins separated by spaces, the clearest win first"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "losses", "4 emoji for losses separated by spaces, the heaviest loss first"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "draw", "Emoji for a drawn match"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "available", "Emoji for a match still to be played"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "unplayable", "Emoji for the squares where a player would meet themselves, and voided matches"))
+                .add_option(CreateCommandOption::new(CommandOptionType::String, "forfeit", "Emoji for a match forfeited by a withdrawn player")),
             ]).await;
         if let Err(why) = result {
             println!("Error setting up global commands: {why:?}");
@@ -714,7 +2481,7 @@ async fn main() {
 
     // Create a new instance of the Client, logging in as a bot.
     let mut client =
-        Client::builder(&token, intents).event_handler(Handler::new()).await.expect("Err creating client");
+        Client::builder(&token, intents).event_handler(Handler::new(Storage::from_env())).await.expect("Err creating client");
 
     // Start listening for events by starting a single shard
     if let Err(why) = client.start().await {
@@ -0,0 +1,432 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Result, Context as _, anyhow};
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+
+use crate::results::{Leg, MatchFormat, MatchResult};
+use crate::standings::{self, Tiebreaker};
+use crate::swiss::SwissState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PlayerId(pub u64);
+impl fmt::Display for PlayerId{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+impl FromStr for PlayerId{
+    type Err = std::num::ParseIntError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(PlayerId)
+    }
+}
+//Stored as a string like Discord ids, reading numbers too
+impl Serialize for PlayerId{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.0)
+    }
+}
+impl<'de> Deserialize<'de> for PlayerId{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl serde::de::Visitor<'_> for Visitor{
+            type Value = PlayerId;
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a string or integer id")
+            }
+            fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                Ok(PlayerId(value))
+            }
+            fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                u64::try_from(value).map(PlayerId).map_err(E::custom)
+            }
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Player{
+    pub name: String,
+    pub id: PlayerId,
+}
+
+pub type Matches = HashMap<(PlayerId, PlayerId), MatchResult>;
+
+//JSON objects need string keys, so the results map is stored as a flat list instead
+pub mod match_list{
+    use super::*;
+    #[derive(Serialize, Deserialize)]
+    struct Entry{
+        x: PlayerId,
+        y: PlayerId,
+        result: MatchResult,
+    }
+    pub fn serialize<S: Serializer>(results: &Matches, serializer: S) -> Result<S::Ok, S::Error>{
+        serializer.collect_seq(results.iter().map(|((x, y), result)| Entry{x: *x, y: *y, result: result.clone()}))
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Matches, D::Error>{
+        let entries = Vec::<Entry>::deserialize(deserializer)?;
+        Ok(entries.into_iter().map(|x| ((x.x, x.y), x.result)).collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Withdrawal{
+    //Every match they were in no longer counts, played or not
+    #[default]
+    Void,
+    //Their unplayed matches are wins for their opponents, played ones stand
+    Forfeit,
+}
+impl Withdrawal{
+    pub fn get(name: &str) -> Option<Self> {
+        match name{
+            "void" => Some(Self::Void),
+            "forfeit" => Some(Self::Forfeit),
+            _ => None,
+        }
+    }
+}
+
+//Whether two (home player, opponent) keys are the same match, either way round unless playing home and away
+pub fn same_match(double: bool, a: (PlayerId, PlayerId), b: (PlayerId, PlayerId)) -> bool{
+    a == b || (!double && a == (b.1, b.0))
+}
+
+//Every player's result against every other, `results[(x, y)]` is row y's result against column x from y's point of view
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Matrix{
+    pub users: Vec<Player>,
+    #[serde(with = "match_list")]
+    pub results: Matches,
+    //Players who don't want Find A Match pings
+    pub disabled_fam: HashSet<PlayerId>,
+    #[serde(default)]
+    pub format: MatchFormat,
+    #[serde(default = "standings::default_tiebreakers")]
+    pub tiebreakers: Vec<Tiebreaker>,
+    #[serde(default)]
+    pub swiss: Option<SwissState>,
+    //Double round-robin, results[(x, y)] is then only y's home match against x
+    #[serde(default)]
+    pub double: bool,
+    //Players who left, whose matches can no longer be reported
+    #[serde(default)]
+    pub withdrawn: HashSet<PlayerId>,
+}
+impl Matrix{
+    pub fn new(users: Vec<Player>, format: MatchFormat, tiebreakers: Vec<Tiebreaker>, swiss_rounds: Option<u32>, double: bool) -> Self{
+        let mut results = HashMap::new();
+        for y in &users{
+            for x in &users{
+                let result = if x == y {MatchResult::Unplayable} else {MatchResult::NotPlayed};
+                results.insert((x.id, y.id), result);
+            }
+        }
+        Matrix{users, results, disabled_fam: HashSet::new(), format, tiebreakers, swiss: swiss_rounds.map(SwissState::new), double, withdrawn: HashSet::new()}
+    }
+
+    pub fn player(&self, id: PlayerId) -> Option<&Player>{
+        self.users.iter().find(|x| x.id == id)
+    }
+    //The player's name, or a mention if they're not in the matrix
+    pub fn name_of(&self, id: PlayerId) -> String{
+        self.player(id).map_or(format!("<@{}>", id), |x| x.name.clone())
+    }
+    //Every result in the player's row, from the player's point of view, plus their away matches in a double round-robin
+    pub fn player_results(&self, player: PlayerId) -> impl Iterator<Item = (PlayerId, MatchResult)> + '_ {
+        let opponents = self.users.iter().filter(move |x| x.id != player);
+        let home = opponents.clone().filter_map(move |x| self.results.get(&(x.id, player)).map(|result| (x.id, result.clone())));
+        let away = opponents.filter(|_| self.double).filter_map(move |x| self.results.get(&(player, x.id)).map(|result| (x.id, result.invert())));
+        home.chain(away)
+    }
+    pub fn byes(&self, player: PlayerId) -> usize {
+        self.swiss.as_ref().map_or(0, |x| x.byes(player))
+    }
+    //The player's result against the opponent, from the player's point of view
+    pub fn result(&self, player: PlayerId, opponent: PlayerId) -> Option<&MatchResult>{
+        self.results.get(&(opponent, player))
+    }
+
+    //The leg a report is for, filling in whichever leg is still to be played when none is given
+    pub fn pick_leg(&self, player: PlayerId, opponent: PlayerId, leg: Option<Leg>) -> Result<Option<Leg>>{
+        let home_result = self.result(player, opponent).context("match result not available - bad user id?")?;
+        let away_result = self.result(opponent, player).context("match result not available - bad user id?")?;
+        match (self.double, leg) {
+            (false, None) => Ok(None),
+            (false, Some(_)) => Err(anyhow!("legs can only be given in a home and away tournament")),
+            (true, Some(leg)) => Ok(Some(leg)),
+            (true, None) if *home_result == MatchResult::NotPlayed => Ok(Some(Leg::Home)),
+            (true, None) if *away_result == MatchResult::NotPlayed => Ok(Some(Leg::Away)),
+            (true, None) => Err(anyhow!("both legs between {} and {} have been reported, pick the leg to overwrite", self.name_of(player), self.name_of(opponent))),
+        }
+    }
+    //The stored home player and opponent of a match, and whether the player was away
+    pub fn match_key(&self, player: PlayerId, opponent: PlayerId, leg: Option<Leg>) -> Result<(PlayerId, PlayerId, bool)> {
+        match (self.double, leg) {
+            (false, Some(_)) => Err(anyhow!("legs can only be given in a home and away tournament")),
+            (_, Some(Leg::Away)) => Ok((opponent, player, true)),
+            _ => Ok((player, opponent, false)),
+        }
+    }
+    //Sets the (home) player's result against the opponent, returning the result it replaced
+    pub fn set_result(&mut self, player: PlayerId, opponent: PlayerId, result: MatchResult) -> Result<MatchResult>{
+        if player == opponent {
+            return Err(anyhow!("trying to report a match played against the same player"));
+        }
+        if let Some(withdrawn) = [player, opponent].into_iter().find(|x| self.withdrawn.contains(x)) {
+            return Err(anyhow!("{} has withdrawn from the tournament", self.name_of(withdrawn)));
+        }
+        if !self.double {
+            let x = self.results.get_mut(&(player, opponent)).context("match not found - bad user id?")?;
+            *x = result.invert();
+        }
+        let x2 = self.results.get_mut(&(opponent, player)).context("reverse match not found - bad user id?")?;
+        Ok(std::mem::replace(x2, result))
+    }
+
+    //Adds a player with every match still to play, except against withdrawn players
+    pub fn add_player(&mut self, player: Player) -> Result<()>{
+        if self.player(player.id).is_some() {
+            return Err(anyhow!("{} is already in the tournament", player.name));
+        }
+        for other in &self.users{
+            //There's no playing someone who already withdrew
+            let result = if self.withdrawn.contains(&other.id) {MatchResult::Unplayable} else {MatchResult::NotPlayed};
+            self.results.insert((other.id, player.id), result.clone());
+            self.results.insert((player.id, other.id), result);
+        }
+        self.results.insert((player.id, player.id), MatchResult::Unplayable);
+        self.users.push(player);
+        Ok(())
+    }
+    pub fn remove_player(&mut self, id: PlayerId) -> Result<Player>{
+        let index = self.users.iter().position(|x| x.id == id).context("player is not in the tournament")?;
+        let player = self.users.remove(index);
+        self.results.retain(|(x, y), _| *x != id && *y != id);
+        self.disabled_fam.remove(&id);
+        self.withdrawn.remove(&id);
+        Ok(player)
+    }
+    //Voids or forfeits a player's matches, returning how many results changed
+    pub fn withdraw(&mut self, id: PlayerId, policy: Withdrawal) -> Result<usize>{
+        let player = self.player(id).context("player is not in the tournament")?.name.clone();
+        if !self.withdrawn.insert(id) {
+            return Err(anyhow!("{} has already withdrawn", player));
+        }
+        let mut changed = 0;
+        for opponent in self.users.iter().map(|x| x.id).filter(|x| *x != id){
+            //(opponent, id) is the player's result against the opponent, (id, opponent) the opponent's against the player
+            for (key, forfeit) in [((opponent, id), MatchResult::Forfeit(false)), ((id, opponent), MatchResult::Forfeit(true))]{
+                let result = self.results.get_mut(&key).context("match not found - bad user id?")?;
+                let new_result = match policy {
+                    _ if self.withdrawn.contains(&opponent) && matches!(result, MatchResult::NotPlayed | MatchResult::Forfeit(_)) => MatchResult::Unplayable,
+                    Withdrawal::Void => MatchResult::Unplayable,
+                    Withdrawal::Forfeit if *result == MatchResult::NotPlayed => forfeit,
+                    Withdrawal::Forfeit => continue,
+                };
+                if *result != new_result {
+                    *result = new_result;
+                    changed += 1;
+                }
+            }
+        }
+        //Both sides of a single round-robin match were counted
+        Ok(if self.double {changed} else {changed/2})
+    }
+
+    //Opponents the player still has a match to play against, only their current opponent in Swiss
+    pub fn unplayed_opponents(&self, player: PlayerId) -> Vec<&Player>{
+        self.users.iter().filter(|opponent| {
+            let paired = self.swiss.as_ref().is_none_or(|x| x.current_opponent(player) == Some(opponent.id))
+                && !self.withdrawn.contains(&opponent.id);
+            //Self is MatchResult::Unplayable so no need to special case it
+            let unplayed = self.results.get(&(player, opponent.id)) == Some(&MatchResult::NotPlayed)
+                || (self.double && self.results.get(&(opponent.id, player)) == Some(&MatchResult::NotPlayed));
+            paired && unplayed
+        }).collect()
+    }
+
+    //Enough messages for the grid to stay under Discord's message length limit
+    pub fn message_count(&self) -> usize{
+        let approx_char_count = match &self.swiss {
+            Some(swiss) => (self.users.len()+1)*(swiss.rounds as usize+3)*25, // One square per round, plus the username and bell
+            None => ((self.users.len()+1)*(self.users.len()+1))*25, // Up to 25 characters per matrix square, plus the username and bell, plus the row of letters
+        };
+        (approx_char_count/1800)+1 //2000 character limit, plus some wiggle room to be safe
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests{
+    use super::*;
+    use proptest::prelude::*;
+
+    pub(crate) fn players(count: u64) -> Vec<Player>{
+        (1..=count).map(|x| Player{name: format!("player{}", x), id: PlayerId(x)}).collect()
+    }
+    pub(crate) fn matrix(count: u64) -> Matrix{
+        Matrix::new(players(count), MatchFormat::default(), standings::default_tiebreakers(), None, false)
+    }
+
+    #[test]
+    fn new_matrix_has_every_match_unplayed() {
+        let matrix = matrix(4);
+        assert_eq!(matrix.results.len(), 16);
+        for x in &matrix.users{
+            for y in &matrix.users{
+                let expected = if x == y {MatchResult::Unplayable} else {MatchResult::NotPlayed};
+                assert_eq!(matrix.results[&(x.id, y.id)], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn reports_fill_in_both_sides() {
+        let mut matrix = matrix(3);
+        let old = matrix.set_result(PlayerId(1), PlayerId(2), MatchResult::Score(2, 1)).unwrap();
+        assert_eq!(old, MatchResult::NotPlayed);
+        assert_eq!(matrix.result(PlayerId(1), PlayerId(2)), Some(&MatchResult::Score(2, 1)));
+        assert_eq!(matrix.result(PlayerId(2), PlayerId(1)), Some(&MatchResult::Score(1, 2)));
+        assert_eq!(matrix.player_results(PlayerId(1)).filter(|(_, x)| x.is_win()).count(), 1);
+        assert!(matrix.set_result(PlayerId(1), PlayerId(1), MatchResult::Score(2, 0)).is_err());
+        assert!(matrix.set_result(PlayerId(1), PlayerId(9), MatchResult::Score(2, 0)).is_err());
+    }
+
+    #[test]
+    fn home_and_away_legs_are_separate_matches() {
+        let mut matrix = Matrix{double: true, ..matrix(2)};
+        assert_eq!(matrix.pick_leg(PlayerId(1), PlayerId(2), None).unwrap(), Some(Leg::Home));
+        matrix.set_result(PlayerId(1), PlayerId(2), MatchResult::Score(2, 0)).unwrap();
+        assert_eq!(matrix.pick_leg(PlayerId(1), PlayerId(2), None).unwrap(), Some(Leg::Away));
+        let (home, away, is_away) = matrix.match_key(PlayerId(1), PlayerId(2), Some(Leg::Away)).unwrap();
+        assert_eq!((home, away, is_away), (PlayerId(2), PlayerId(1), true));
+        matrix.set_result(home, away, MatchResult::Score(2, 1)).unwrap();
+        assert!(matrix.pick_leg(PlayerId(1), PlayerId(2), None).is_err());
+        //Player 1 won at home and lost away
+        let results: Vec<MatchResult> = matrix.player_results(PlayerId(1)).map(|(_, x)| x).collect();
+        assert_eq!(results, vec![MatchResult::Score(2, 0), MatchResult::Score(1, 2)]);
+        assert!(matrix.unplayed_opponents(PlayerId(1)).is_empty());
+    }
+
+    #[test]
+    fn legs_need_a_double_round_robin() {
+        let matrix = matrix(2);
+        assert!(matrix.pick_leg(PlayerId(1), PlayerId(2), Some(Leg::Home)).is_err());
+        assert!(matrix.match_key(PlayerId(1), PlayerId(2), Some(Leg::Away)).is_err());
+        assert_eq!(matrix.pick_leg(PlayerId(1), PlayerId(2), None).unwrap(), None);
+    }
+
+    #[test]
+    fn unplayed_opponents_skips_played_matches_and_self() {
+        let mut matrix = matrix(4);
+        matrix.set_result(PlayerId(1), PlayerId(3), MatchResult::Draw).unwrap();
+        let opponents: Vec<PlayerId> = matrix.unplayed_opponents(PlayerId(1)).iter().map(|x| x.id).collect();
+        assert_eq!(opponents, vec![PlayerId(2), PlayerId(4)]);
+    }
+
+    #[test]
+    fn players_join_with_every_match_to_play_and_leave_with_their_results() {
+        let mut matrix = matrix(3);
+        matrix.set_result(PlayerId(1), PlayerId(2), MatchResult::Score(2, 0)).unwrap();
+        matrix.set_result(PlayerId(2), PlayerId(3), MatchResult::Score(2, 1)).unwrap();
+        matrix.add_player(Player{name: "late".to_string(), id: PlayerId(4)}).unwrap();
+        assert!(matrix.add_player(Player{name: "again".to_string(), id: PlayerId(4)}).is_err());
+        assert_eq!(matrix.results.len(), 16);
+        assert_eq!(matrix.unplayed_opponents(PlayerId(4)).len(), 3);
+        assert_eq!(matrix.result(PlayerId(4), PlayerId(4)), Some(&MatchResult::Unplayable));
+
+        matrix.disabled_fam.insert(PlayerId(2));
+        assert_eq!(matrix.remove_player(PlayerId(2)).unwrap().name, "player2");
+        assert!(matrix.remove_player(PlayerId(2)).is_err());
+        assert_eq!(matrix.results.len(), 9);
+        assert!(matrix.disabled_fam.is_empty());
+        assert_eq!(matrix.player_results(PlayerId(1)).filter(|(_, x)| x.is_played()).count(), 0);
+    }
+
+    #[test]
+    fn forfeits_go_to_the_opponents_of_a_withdrawn_player() {
+        let mut matrix = matrix(4);
+        matrix.set_result(PlayerId(1), PlayerId(2), MatchResult::Score(2, 0)).unwrap();
+        assert_eq!(matrix.withdraw(PlayerId(1), Withdrawal::Forfeit).unwrap(), 2);
+        assert!(matrix.withdraw(PlayerId(1), Withdrawal::Forfeit).is_err());
+        assert_eq!(matrix.result(PlayerId(1), PlayerId(2)), Some(&MatchResult::Score(2, 0)));
+        assert_eq!(matrix.result(PlayerId(1), PlayerId(3)), Some(&MatchResult::Forfeit(false)));
+        assert_eq!(matrix.result(PlayerId(3), PlayerId(1)), Some(&MatchResult::Forfeit(true)));
+        assert!(matrix.set_result(PlayerId(4), PlayerId(1), MatchResult::Score(2, 0)).is_err());
+        assert!(matrix.unplayed_opponents(PlayerId(3)).iter().all(|x| x.id != PlayerId(1)));
+
+        //A forfeit between two withdrawn players is void
+        assert_eq!(matrix.withdraw(PlayerId(3), Withdrawal::Forfeit).unwrap(), 3);
+        assert_eq!(matrix.result(PlayerId(3), PlayerId(1)), Some(&MatchResult::Unplayable));
+        assert_eq!(matrix.result(PlayerId(4), PlayerId(3)), Some(&MatchResult::Forfeit(true)));
+    }
+
+    #[test]
+    fn forfeiting_keeps_the_score_between_two_withdrawn_players() {
+        let mut matrix = matrix(3);
+        matrix.set_result(PlayerId(1), PlayerId(2), MatchResult::Score(2, 1)).unwrap();
+        assert_eq!(matrix.withdraw(PlayerId(1), Withdrawal::Forfeit).unwrap(), 1);
+        assert_eq!(matrix.withdraw(PlayerId(2), Withdrawal::Forfeit).unwrap(), 1);
+        assert_eq!(matrix.result(PlayerId(1), PlayerId(2)), Some(&MatchResult::Score(2, 1)));
+        assert_eq!(matrix.result(PlayerId(2), PlayerId(1)), Some(&MatchResult::Score(1, 2)));
+        assert_eq!(matrix.result(PlayerId(3), PlayerId(2)), Some(&MatchResult::Forfeit(true)));
+    }
+
+    #[test]
+    fn voiding_drops_played_matches_too() {
+        let mut matrix = Matrix{double: true, ..matrix(3)};
+        matrix.set_result(PlayerId(1), PlayerId(2), MatchResult::Score(2, 0)).unwrap();
+        assert_eq!(matrix.withdraw(PlayerId(2), Withdrawal::Void).unwrap(), 4);
+        assert!(matrix.player_results(PlayerId(1)).all(|(_, x)| x != MatchResult::Score(2, 0)));
+        assert_eq!(matrix.unplayed_opponents(PlayerId(1)).len(), 1);
+        assert_eq!(Withdrawal::get("forfeit"), Some(Withdrawal::Forfeit));
+    }
+
+    #[test]
+    fn ids_are_stored_as_strings_and_read_from_numbers() {
+        assert_eq!(serde_json::to_string(&PlayerId(183433751689166850)).unwrap(), "\"183433751689166850\"");
+        assert_eq!(serde_json::from_str::<PlayerId>("\"42\"").unwrap(), PlayerId(42));
+        assert_eq!(serde_json::from_str::<PlayerId>("42").unwrap(), PlayerId(42));
+        assert!(serde_json::from_str::<PlayerId>("\"x\"").is_err());
+    }
+
+    #[test]
+    fn same_match_only_ignores_order_in_single_round_robins() {
+        let (a, b) = (PlayerId(1), PlayerId(2));
+        assert!(same_match(false, (a, b), (b, a)));
+        assert!(!same_match(true, (a, b), (b, a)));
+        assert!(same_match(true, (a, b), (a, b)));
+    }
+
+    proptest!{
+        #[test]
+        fn matrix_survives_a_json_round_trip(reports in prop::collection::vec((1u64..=5, 1u64..=5, 0u8..=2, 0u8..=2), 0..20)) {
+            let mut matrix = matrix(5);
+            for (x, y, a, b) in reports{
+                let _ = matrix.set_result(PlayerId(x), PlayerId(y), MatchResult::Score(a, b));
+            }
+            let json = serde_json::to_string(&matrix).unwrap();
+            let loaded: Matrix = serde_json::from_str(&json).unwrap();
+            prop_assert_eq!(loaded.results, matrix.results);
+            prop_assert_eq!(loaded.users, matrix.users);
+        }
+
+        #[test]
+        fn results_stay_mirrored(reports in prop::collection::vec((1u64..=4, 1u64..=4, 0u8..=3, 0u8..=3), 0..30)) {
+            let mut matrix = matrix(4);
+            for (x, y, a, b) in reports{
+                let _ = matrix.set_result(PlayerId(x), PlayerId(y), MatchResult::Score(a, b));
+            }
+            for ((x, y), result) in &matrix.results{
+                prop_assert_eq!(&matrix.results[&(*y, *x)].invert(), result);
+            }
+        }
+    }
+}
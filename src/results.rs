@@ -0,0 +1,194 @@
+use anyhow::{Result, anyhow};
+use serde::{Serialize, Deserialize};
+
+//Results are stored as games won-lost from one player's point of view
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MatchResult{
+    NotPlayed, Score(u8, u8), Draw, Unplayable,
+    //Awarded without playing because one of the players withdrew, true for the player given the win
+    Forfeit(bool),
+}
+//How many different icons a theme has for wins, and for losses
+pub const RUNGS: usize = 4;
+impl MatchResult{
+    //Reads a typed score such as `2-1`, `draw` or `0-0`
+    pub fn get(result: &str) -> Option<Self> {
+        match result{
+            "0-0" => return Some(Self::NotPlayed),
+            "draw" => return Some(Self::Draw),
+            "forfeit win" => return Some(Self::Forfeit(true)),
+            "forfeit loss" => return Some(Self::Forfeit(false)),
+            _ => {}
+        }
+        match result.split_once('-').map(|(x, y)| (x.trim().parse(), y.trim().parse())){
+            Some((Ok(x), Ok(y))) => Some(Self::Score(x, y)),
+            _ => None,
+        }
+    }
+    pub fn invert(&self) -> Self {
+        match self{
+            Self::Score(x, y) => Self::Score(*y, *x),
+            Self::Forfeit(won) => Self::Forfeit(!won),
+            other => other.clone(),
+        }
+    }
+    pub fn to_str(&self) -> String {
+        match self{
+            Self::NotPlayed => "0-0".to_string(),
+            Self::Score(x, y) => format!("{}-{}", x, y),
+            Self::Draw => "draw".to_string(),
+            Self::Unplayable => "ERROR".to_string(),
+            Self::Forfeit(true) => "forfeit win".to_string(),
+            Self::Forfeit(false) => "forfeit loss".to_string(),
+        }
+    }
+    pub fn is_win(&self) -> bool {
+        matches!(self, Self::Score(x, y) if x > y) || *self == Self::Forfeit(true)
+    }
+    //Forfeits count as played, so they show in each player's record
+    pub fn is_played(&self) -> bool {
+        matches!(self, Self::Score(..) | Self::Draw | Self::Forfeit(_))
+    }
+}
+
+//How many game wins end a match, so best of 3 is first to 2
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchFormat{
+    pub first_to: u8,
+    //Whether a match can end in a draw, e.g. on time
+    #[serde(default)]
+    pub draws: bool,
+}
+impl Default for MatchFormat{
+    fn default() -> Self {
+        MatchFormat{first_to: 2, draws: false}
+    }
+}
+impl MatchFormat{
+    //Best of 7, so every score has its own icon and reads back from the grid unchanged
+    pub const MAX_FIRST_TO: u8 = RUNGS as u8;
+
+    pub fn best_of(games: u8) -> Result<Self> {
+        if games.is_multiple_of(2) || games > Self::MAX_FIRST_TO*2-1 {
+            return Err(anyhow!("best of {} is not a supported match format", games));
+        }
+        Ok(MatchFormat{first_to: games.div_ceil(2), draws: false})
+    }
+    pub fn name(&self) -> String {
+        format!("best of {}", self.first_to*2-1)
+    }
+    pub fn description(&self) -> String {
+        if self.draws {self.name()+", draws allowed"} else {self.name()}
+    }
+    pub fn allows(&self, result: &MatchResult) -> bool {
+        match result{
+            MatchResult::NotPlayed => true,
+            MatchResult::Score(x, y) => x.max(y) == &self.first_to && x.min(y) < &self.first_to,
+            MatchResult::Draw => self.draws,
+            //Only given by a withdrawal, never reported
+            MatchResult::Unplayable | MatchResult::Forfeit(_) => false,
+        }
+    }
+    //Which of a theme's win or loss icons shows a match where the loser won the given games
+    pub fn rung(&self, loser_games: u8) -> usize {
+        loser_games.min(self.first_to.saturating_sub(1)) as usize
+    }
+    pub fn loser_games(&self, rung: usize) -> u8 {
+        (0..self.first_to).find(|x| self.rung(*x) == rung).unwrap_or(0)
+    }
+    //Every decisive score, wins first
+    pub fn scores(&self) -> Vec<MatchResult> {
+        let wins = (0..self.first_to).map(|x| MatchResult::Score(self.first_to, x));
+        let losses = (0..self.first_to).rev().map(|x| MatchResult::Score(x, self.first_to));
+        wins.chain(losses).collect()
+    }
+}
+
+//Which of a pair's two matches in a double round-robin, from the reporting player's point of view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Leg{
+    Home, Away
+}
+impl Leg{
+    pub fn get(name: &str) -> Option<Self> {
+        match name{
+            "home" => Some(Self::Home),
+            "away" => Some(Self::Away),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use proptest::prelude::*;
+
+    fn formats() -> impl Iterator<Item = MatchFormat> {
+        (1..=MatchFormat::MAX_FIRST_TO).flat_map(|first_to| [false, true].map(|draws| MatchFormat{first_to, draws}))
+    }
+
+    #[test]
+    fn best_of_only_takes_odd_supported_lengths() {
+        assert_eq!(MatchFormat::best_of(3).unwrap(), MatchFormat::default());
+        assert_eq!(MatchFormat::best_of(7).unwrap().first_to, 4);
+        assert!(MatchFormat::best_of(4).is_err());
+        assert!(MatchFormat::best_of(9).is_err());
+    }
+
+    #[test]
+    fn every_score_is_allowed_and_parses_back() {
+        for format in formats(){
+            let scores = format.scores();
+            assert_eq!(scores.len(), format.first_to as usize * 2);
+            for score in scores{
+                assert!(format.allows(&score), "{:?} {:?}", format, score);
+                assert_eq!(MatchResult::get(&score.to_str()), Some(score));
+            }
+        }
+    }
+
+    #[test]
+    fn draws_need_the_format_to_allow_them() {
+        assert!(!MatchFormat::default().allows(&MatchResult::Draw));
+        assert!(MatchFormat{draws: true, ..Default::default()}.allows(&MatchResult::Draw));
+        assert!(!MatchFormat::default().allows(&MatchResult::Unplayable));
+    }
+
+    #[test]
+    fn forfeits_count_as_played_but_cannot_be_reported() {
+        let forfeit = MatchResult::Forfeit(true);
+        assert!(forfeit.is_win() && forfeit.is_played());
+        assert_eq!(forfeit.invert(), MatchResult::Forfeit(false));
+        assert!(!forfeit.invert().is_win());
+        assert_eq!(MatchResult::get(&forfeit.invert().to_str()), Some(MatchResult::Forfeit(false)));
+        assert!(!MatchFormat{draws: true, ..Default::default()}.allows(&forfeit));
+    }
+
+    proptest!{
+        #[test]
+        fn invert_is_its_own_inverse(x in 0u8..10, y in 0u8..10) {
+            let result = MatchResult::Score(x, y);
+            prop_assert_eq!(result.invert().invert(), result.clone());
+            prop_assert!(!(result.is_win() && result.invert().is_win()));
+        }
+
+        #[test]
+        fn allowed_scores_have_exactly_one_winner(first_to in 1u8..=MatchFormat::MAX_FIRST_TO, x in 0u8..10, y in 0u8..10) {
+            let format = MatchFormat{first_to, draws: false};
+            let result = MatchResult::Score(x, y);
+            if format.allows(&result) {
+                prop_assert!(result.is_win() != result.invert().is_win());
+                prop_assert!(format.scores().contains(&result));
+            }
+        }
+
+        #[test]
+        fn rungs_never_decrease(first_to in 1u8..=MatchFormat::MAX_FIRST_TO) {
+            let format = MatchFormat{first_to, draws: false};
+            let rungs: Vec<usize> = (0..first_to).map(|x| format.rung(x)).collect();
+            prop_assert!(rungs.windows(2).all(|x| x[0] <= x[1]));
+            prop_assert!(rungs.iter().all(|x| *x < RUNGS));
+        }
+    }
+}
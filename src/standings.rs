@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use serde::{Serialize, Deserialize};
+
+use crate::matrix::{Matrix, PlayerId};
+use crate::results::MatchResult;
+
+const WIN_POINTS: u32 = 3;
+const DRAW_POINTS: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tiebreaker{
+    HeadToHead, GameDifferential, SonnebornBerger, StrengthOfSchedule
+}
+impl Tiebreaker{
+    pub const DEFAULT_ORDER: [Tiebreaker; 4] = [Self::HeadToHead, Self::GameDifferential, Self::SonnebornBerger, Self::StrengthOfSchedule];
+
+    pub fn get(name: &str) -> Option<Self> {
+        match name{
+            "h2h" => Some(Self::HeadToHead),
+            "games" => Some(Self::GameDifferential),
+            "sb" => Some(Self::SonnebornBerger),
+            "sos" => Some(Self::StrengthOfSchedule),
+            _ => None,
+        }
+    }
+    pub fn to_str(self) -> &'static str {
+        match self{
+            Self::HeadToHead => "h2h",
+            Self::GameDifferential => "games",
+            Self::SonnebornBerger => "sb",
+            Self::StrengthOfSchedule => "sos",
+        }
+    }
+    //Parses a comma separated list such as `h2h, games, sb`
+    pub fn parse_list(list: &str) -> Result<Vec<Self>> {
+        let mut tiebreakers = Vec::new();
+        for name in list.split(',').map(|x| x.trim().to_lowercase()).filter(|x| !x.is_empty()){
+            let tiebreaker = Self::get(&name).ok_or_else(|| anyhow!("unknown tiebreaker {}, expected h2h, games, sb or sos", name))?;
+            if tiebreakers.contains(&tiebreaker) {
+                return Err(anyhow!("tiebreaker {} listed twice", name));
+            }
+            tiebreakers.push(tiebreaker);
+        }
+        Ok(tiebreakers)
+    }
+}
+pub fn default_tiebreakers() -> Vec<Tiebreaker>{
+    Tiebreaker::DEFAULT_ORDER.to_vec()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Standing{
+    pub user: PlayerId,
+    pub place: usize,
+    pub points: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    pub game_wins: u32,
+    pub game_losses: u32,
+    //Points of every opponent beaten plus half the points of every opponent drawn
+    pub sonneborn_berger: f64,
+    //Total points of every opponent played
+    pub strength_of_schedule: f64,
+}
+impl Standing{
+    pub fn game_differential(&self) -> i64 {
+        self.game_wins as i64 - self.game_losses as i64
+    }
+}
+
+fn match_points(result: &MatchResult) -> u32 {
+    match result{
+        _ if result.is_win() => WIN_POINTS,
+        MatchResult::Draw => DRAW_POINTS,
+        _ => 0,
+    }
+}
+
+//Standings of every player, best first. Players still level after every tiebreaker share a place.
+pub fn compute(matrix: &Matrix) -> Vec<Standing> {
+    let mut standings: HashMap<PlayerId, Standing> = HashMap::new();
+    for player in &matrix.users{
+        let mut standing = Standing{user: player.id, place: 0, points: 0, wins: 0, draws: 0, losses: 0, game_wins: 0, game_losses: 0,
+            sonneborn_berger: 0.0, strength_of_schedule: 0.0};
+        //A bye is worth a win
+        let byes = matrix.byes(player.id) as u32;
+        standing.points += byes*WIN_POINTS;
+        standing.wins += byes;
+        for (_, result) in matrix.player_results(player.id){
+            standing.points += match_points(&result);
+            match result{
+                MatchResult::Score(x, y) => {
+                    standing.game_wins += x as u32;
+                    standing.game_losses += y as u32;
+                    if x > y {standing.wins += 1} else {standing.losses += 1}
+                },
+                MatchResult::Draw => standing.draws += 1,
+                //No games were played, so game differential is untouched
+                MatchResult::Forfeit(won) => if won {standing.wins += 1} else {standing.losses += 1},
+                _ => {},
+            }
+        }
+        standings.insert(player.id, standing);
+    }
+    let points: HashMap<PlayerId, u32> = standings.iter().map(|(id, x)| (*id, x.points)).collect();
+    for (id, standing) in standings.iter_mut(){
+        for (opponent, result) in matrix.player_results(*id){
+            let opponent_points = points[&opponent] as f64;
+            if result.is_played() {
+                standing.strength_of_schedule += opponent_points;
+            }
+            if result.is_win() {
+                standing.sonneborn_berger += opponent_points;
+            } else if result == MatchResult::Draw {
+                standing.sonneborn_berger += opponent_points/2.0;
+            }
+        }
+    }
+
+    let mut by_points: Vec<Standing> = matrix.users.iter().filter_map(|x| standings.remove(&x.id)).collect();
+    by_points.sort_by_key(|x| std::cmp::Reverse(x.points));
+    let mut ordered = Vec::new();
+    for group in by_points.chunk_by(|a, b| a.points == b.points){
+        for tied_group in break_ties(matrix, group.to_vec(), &matrix.tiebreakers){
+            let place = ordered.len()+1;
+            ordered.extend(tied_group.into_iter().map(|x| Standing{place, ..x}));
+        }
+    }
+    ordered
+}
+
+//Splits a group level on points into groups that are still level, in finishing order
+fn break_ties(matrix: &Matrix, group: Vec<Standing>, tiebreakers: &[Tiebreaker]) -> Vec<Vec<Standing>> {
+    let Some((tiebreaker, remaining)) = tiebreakers.split_first() else { return vec![group] };
+    if group.len() <= 1 {
+        return vec![group];
+    }
+    let tied: Vec<PlayerId> = group.iter().map(|x| x.user).collect();
+    let mut keyed: Vec<(f64, Standing)> = group.into_iter().map(|x| (tiebreak_value(matrix, &x, &tied, *tiebreaker), x)).collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut groups = Vec::new();
+    for subgroup in keyed.chunk_by(|a, b| a.0 == b.0){
+        groups.extend(break_ties(matrix, subgroup.iter().map(|(_, x)| x.clone()).collect(), remaining));
+    }
+    groups
+}
+
+fn tiebreak_value(matrix: &Matrix, standing: &Standing, tied: &[PlayerId], tiebreaker: Tiebreaker) -> f64 {
+    match tiebreaker{
+        //Head to head only counts matches between the players that are tied
+        Tiebreaker::HeadToHead => matrix.player_results(standing.user)
+            .filter(|(opponent, _)| tied.contains(opponent))
+            .map(|(_, result)| match_points(&result) as f64).sum(),
+        Tiebreaker::GameDifferential => standing.game_differential() as f64,
+        Tiebreaker::SonnebornBerger => standing.sonneborn_berger,
+        Tiebreaker::StrengthOfSchedule => standing.strength_of_schedule,
+    }
+}
+
+pub fn render(title: &str, matrix: &Matrix, standings: &[Standing]) -> String {
+    let mut output = format!("Standings for {} (tiebreakers: {})", title,
+        if matrix.tiebreakers.is_empty() {"none".to_string()} else {matrix.tiebreakers.iter().map(|x| x.to_str()).collect::<Vec<_>>().join(", ")});
+    for standing in standings{
+        let name = matrix.users.iter().find(|x| x.id == standing.user).map_or("?", |x| &x.name);
+        output += &format!("\n{}. {} - {} pts ({}-{}-{}), games {:+}, sb {}, sos {}", standing.place, name, standing.points,
+            standing.wins, standing.draws, standing.losses, standing.game_differential(), standing.sonneborn_berger, standing.strength_of_schedule);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::matrix::tests::matrix;
+    use proptest::prelude::*;
+
+    fn order(standings: &[Standing]) -> Vec<(u64, usize)> {
+        standings.iter().map(|x| (x.user.0, x.place)).collect()
+    }
+
+    #[test]
+    fn wins_and_draws_are_worth_points() {
+        let mut matrix = matrix(3);
+        matrix.format.draws = true;
+        matrix.set_result(PlayerId(1), PlayerId(2), MatchResult::Score(2, 1)).unwrap();
+        matrix.set_result(PlayerId(1), PlayerId(3), MatchResult::Draw).unwrap();
+        let standings = compute(&matrix);
+        let first = &standings[0];
+        assert_eq!((first.user, first.points, first.wins, first.draws, first.losses), (PlayerId(1), 4, 1, 1, 0));
+        assert_eq!((first.game_wins, first.game_losses), (2, 1));
+        assert_eq!(order(&standings), vec![(1, 1), (3, 2), (2, 3)]);
+    }
+
+    #[test]
+    fn forfeits_are_wins_without_games() {
+        let mut matrix = matrix(3);
+        matrix.set_result(PlayerId(2), PlayerId(3), MatchResult::Score(2, 1)).unwrap();
+        matrix.withdraw(PlayerId(1), crate::matrix::Withdrawal::Forfeit).unwrap();
+        let standings = compute(&matrix);
+        let second = standings.iter().find(|x| x.user == PlayerId(3)).unwrap();
+        assert_eq!((second.points, second.wins, second.losses, second.game_differential()), (3, 1, 1, -1));
+        assert_eq!(order(&standings), vec![(2, 1), (3, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn head_to_head_breaks_a_tie_before_games() {
+        let mut matrix = matrix(4);
+        //1 and 2 both win twice, 2 by more games but 1 beat 2
+        matrix.set_result(PlayerId(1), PlayerId(2), MatchResult::Score(2, 1)).unwrap();
+        matrix.set_result(PlayerId(1), PlayerId(3), MatchResult::Score(2, 1)).unwrap();
+        matrix.set_result(PlayerId(4), PlayerId(1), MatchResult::Score(2, 1)).unwrap();
+        matrix.set_result(PlayerId(2), PlayerId(3), MatchResult::Score(2, 0)).unwrap();
+        matrix.set_result(PlayerId(2), PlayerId(4), MatchResult::Score(2, 0)).unwrap();
+        let standings = compute(&matrix);
+        assert_eq!(standings[0].user, PlayerId(1));
+        assert_eq!(standings[1].user, PlayerId(2));
+
+        matrix.tiebreakers = vec![Tiebreaker::GameDifferential];
+        let standings = compute(&matrix);
+        assert_eq!(standings[0].user, PlayerId(2));
+    }
+
+    #[test]
+    fn players_level_on_everything_share_a_place() {
+        let matrix = matrix(3);
+        assert_eq!(order(&compute(&matrix)), vec![(1, 1), (2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn tiebreaker_lists_parse() {
+        assert_eq!(Tiebreaker::parse_list("games, H2H").unwrap(), vec![Tiebreaker::GameDifferential, Tiebreaker::HeadToHead]);
+        assert!(Tiebreaker::parse_list("").unwrap().is_empty());
+        assert!(Tiebreaker::parse_list("sb, sb").is_err());
+        assert!(Tiebreaker::parse_list("elo").is_err());
+    }
+
+    proptest!{
+        #[test]
+        fn places_follow_points(results in proptest::collection::vec(0u8..3, 15)) {
+            let mut matrix = matrix(6);
+            let pairs = (1..=6u64).flat_map(|x| (x+1..=6).map(move |y| (PlayerId(x), PlayerId(y))));
+            for ((player, opponent), result) in pairs.zip(results){
+                let result = match result {
+                    0 => MatchResult::NotPlayed,
+                    1 => MatchResult::Score(2, 0),
+                    _ => MatchResult::Score(1, 2),
+                };
+                matrix.set_result(player, opponent, result).unwrap();
+            }
+            let standings = compute(&matrix);
+            prop_assert_eq!(standings.len(), 6);
+            prop_assert_eq!(standings.iter().map(|x| x.wins).sum::<u32>(), standings.iter().map(|x| x.losses).sum::<u32>());
+            for pair in standings.windows(2){
+                prop_assert!(pair[0].points >= pair[1].points);
+                prop_assert!(pair[0].place <= pair[1].place);
+                if pair[0].place == pair[1].place {
+                    prop_assert_eq!(pair[0].points, pair[1].points);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serenity::all::*;
+use anyhow::{Result, Context as _};
+use serde::{Serialize, Deserialize};
+
+use crate::{GuildConfig, MatchMatrix, MatchMatrixSetup, PlayoffBracket};
+
+//Everything stored for a single guild, one JSON file per guild
+#[derive(Default, Serialize, Deserialize)]
+pub struct GuildState{
+    //Saved as null when the guild has none
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub setups: HashMap<String, MatchMatrixSetup>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub matrices: HashMap<String, MatchMatrix>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub brackets: HashMap<String, PlayoffBracket>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub config: GuildConfig,
+}
+fn null_as_empty<'de, D: serde::Deserializer<'de>, T: Default + Deserialize<'de>>(deserializer: D) -> Result<T, D::Error>{
+    Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+}
+#[derive(Serialize)]
+pub struct GuildStateRef<'a>{
+    pub setups: Option<&'a HashMap<String, MatchMatrixSetup>>,
+    pub matrices: Option<&'a HashMap<String, MatchMatrix>>,
+    pub brackets: Option<&'a HashMap<String, PlayoffBracket>>,
+    pub config: Option<&'a GuildConfig>,
+}
+impl GuildStateRef<'_>{
+    fn is_empty(&self) -> bool{
+        self.setups.is_none_or(|x| x.is_empty()) && self.matrices.is_none_or(|x| x.is_empty()) && self.brackets.is_none_or(|x| x.is_empty())
+            && self.config.is_none_or(|x| x.is_empty())
+    }
+}
+
+pub struct Storage{
+    dir: PathBuf,
+}
+impl Storage{
+    pub fn new(dir: PathBuf) -> Self{
+        Storage{dir}
+    }
+    //Data directory set via `REMA_DATA_DIR`, defaulting to `./data`
+    pub fn from_env() -> Self{
+        Self::new(std::env::var_os("REMA_DATA_DIR").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("data")))
+    }
+
+    fn guild_path(&self, guild: GuildId) -> PathBuf{
+        self.dir.join(format!("{}.json", guild))
+    }
+
+    pub async fn save(&self, guild: GuildId, state: &GuildStateRef<'_>) -> Result<()>{
+        let path = self.guild_path(guild);
+        if state.is_empty(){
+            if tokio::fs::try_exists(&path).await? {
+                tokio::fs::remove_file(&path).await?;
+            }
+            return Ok(());
+        }
+        tokio::fs::create_dir_all(&self.dir).await.context("creating data directory")?;
+        let json = serde_json::to_vec_pretty(state)?;
+        //Write then rename so a crash mid-write can't leave a truncated file behind
+        let tmp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, json).await.context(format!("writing {}", tmp_path.display()))?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        Ok(())
+    }
+
+    //Bad files are moved aside to `<id>.json.broken` so the next save doesn't overwrite them
+    pub async fn load_all(&self) -> Result<Vec<(GuildId, GuildState)>>{
+        let mut guilds = Vec::new();
+        if !tokio::fs::try_exists(&self.dir).await? {
+            return Ok(guilds);
+        }
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await?{
+            let path = entry.path();
+            if path.extension().is_none_or(|x| x != "json") { continue }
+            let Some(guild) = path.file_stem().and_then(|x| x.to_str()).and_then(|x| x.parse().ok()).filter(|x| *x != 0) else { continue };
+            let state = match tokio::fs::read(&path).await {
+                Ok(json) => serde_json::from_slice(&json).context(format!("parsing {}", path.display())),
+                Err(why) => Err(why).context(format!("reading {}", path.display())),
+            };
+            match state {
+                Ok(state) => guilds.push((GuildId::new(guild), state)),
+                Err(why) => {
+                    println!("Skipping saved guild {guild}: {why:#}");
+                    if let Err(why) = tokio::fs::rename(&path, path.with_extension("json.broken")).await{
+                        println!("Error moving {} aside: {why}", path.display());
+                    }
+                },
+            }
+        }
+        Ok(guilds)
+    }
+}
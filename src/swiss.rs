@@ -0,0 +1,212 @@
+use std::collections::HashSet;
+
+use anyhow::{Result, anyhow};
+use serde::{Serialize, Deserialize};
+
+use crate::grid::{Marks, BYE_ICON};
+use crate::matrix::{Matrix, PlayerId};
+use crate::standings;
+use crate::theme::Theme;
+
+//A player and their opponent for one round, with no opponent meaning a bye
+pub type Pairing = (PlayerId, Option<PlayerId>);
+
+//Gives up on avoiding rematches rather than searching forever
+const MAX_PAIRING_STEPS: usize = 100_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwissState{
+    pub rounds: u32,
+    //Pairings of every round so far, the last entry is the current round
+    pub pairings: Vec<Vec<Pairing>>,
+}
+impl SwissState{
+    pub fn new(rounds: u32) -> Self{
+        SwissState{rounds, pairings: Vec::new()}
+    }
+    //Enough rounds to find a single undefeated player
+    pub fn default_rounds(player_count: usize) -> u32{
+        player_count.max(2).next_power_of_two().ilog2()
+    }
+    pub fn round(&self) -> usize{
+        self.pairings.len()
+    }
+    pub fn is_finished(&self) -> bool{
+        self.round() >= self.rounds as usize
+    }
+    pub fn current_round(&self) -> &[Pairing]{
+        self.pairings.last().map_or(&[], |x| x.as_slice())
+    }
+    pub fn has_played(&self, player: PlayerId, opponent: PlayerId) -> bool{
+        self.pairings.iter().flatten().any(|x| *x == (player, Some(opponent)) || *x == (opponent, Some(player)))
+    }
+    pub fn in_current_round(&self, player: PlayerId, opponent: PlayerId) -> bool{
+        self.current_round().iter().any(|x| *x == (player, Some(opponent)) || *x == (opponent, Some(player)))
+    }
+    pub fn byes(&self, player: PlayerId) -> usize{
+        self.pairings.iter().flatten().filter(|x| **x == (player, None)).count()
+    }
+    pub fn current_opponent(&self, player: PlayerId) -> Option<PlayerId>{
+        self.opponent_in_round(self.round().checked_sub(1)?, player).flatten()
+    }
+    //The player's opponent in the given round, None for a bye and also None if they weren't paired
+    pub fn opponent_in_round(&self, round: usize, player: PlayerId) -> Option<Option<PlayerId>>{
+        self.pairings.get(round)?.iter().find_map(|(x, y)| match (x, y) {
+            _ if *x == player => Some(*y),
+            (x, Some(y)) if *y == player => Some(Some(*x)),
+            _ => None,
+        })
+    }
+}
+
+pub fn round_complete(matrix: &Matrix, swiss: &SwissState) -> bool{
+    swiss.current_round().iter().all(|(player, opponent)| match opponent {
+        Some(opponent) => matrix.results.get(&(*opponent, *player)).is_some_and(|x| x.is_played()),
+        None => true,
+    })
+}
+
+//Pairs the next round by score group, best first, avoiding rematches and repeat byes where possible
+pub fn pair_next_round(matrix: &Matrix, swiss: &SwissState) -> Result<Vec<Pairing>>{
+    if swiss.is_finished() {
+        return Err(anyhow!("all {} rounds have already been paired", swiss.rounds));
+    }
+    let mut players: Vec<PlayerId> = standings::compute(matrix).iter().map(|x| x.user).collect();
+    let mut pairings = Vec::new();
+    if players.len() % 2 == 1 {
+        //The lowest ranked player with the fewest byes so far sits out
+        let fewest_byes = players.iter().map(|x| swiss.byes(*x)).min().unwrap_or(0);
+        let bye_index = players.iter().rposition(|x| swiss.byes(*x) == fewest_byes).unwrap_or(players.len()-1);
+        pairings.push((players.remove(bye_index), None));
+    }
+    let mut steps = 0;
+    let matches = pair_without_rematches(&players, &mut HashSet::new(), swiss, &mut steps)
+        .unwrap_or_else(|| players.chunks(2).map(|x| (x[0], x[1])).collect());
+    pairings.splice(0..0, matches.into_iter().map(|(x, y)| (x, Some(y))));
+    Ok(pairings)
+}
+
+fn pair_without_rematches(players: &[PlayerId], used: &mut HashSet<PlayerId>, swiss: &SwissState, steps: &mut usize) -> Option<Vec<(PlayerId, PlayerId)>>{
+    let Some(first) = players.iter().find(|x| !used.contains(*x)) else { return Some(Vec::new()) };
+    used.insert(*first);
+    //Players are in standings order, so the first free candidate is the closest in score
+    let candidates: Vec<PlayerId> = players.iter().filter(|x| !used.contains(*x)).copied().collect();
+    for opponent in &candidates{
+        *steps += 1;
+        if *steps > MAX_PAIRING_STEPS { break }
+        if swiss.has_played(*first, *opponent) { continue }
+        used.insert(*opponent);
+        if let Some(mut rest) = pair_without_rematches(players, used, swiss, steps){
+            rest.insert(0, (*first, *opponent));
+            return Some(rest);
+        }
+        used.remove(opponent);
+    }
+    used.remove(first);
+    None
+}
+
+pub fn render_pairings(matrix: &Matrix, swiss: &SwissState) -> String{
+    let mut output = format!("Round {} of {} pairings:", swiss.round(), swiss.rounds);
+    for (player, opponent) in swiss.current_round(){
+        match opponent {
+            Some(opponent) => output += &format!("\n<@{}> vs <@{}>", player, opponent),
+            None => output += &format!("\n{} has a bye", matrix.name_of(*player)),
+        }
+    }
+    output
+}
+
+//One grid row per player: their result in each round, from their point of view
+pub fn render_row(matrix: &Matrix, swiss: &SwissState, player: PlayerId, theme: &Theme, marks: &Marks) -> String{
+    let mut row = String::new();
+    for round in 0..swiss.round(){
+        row += match swiss.opponent_in_round(round, player) {
+            Some(Some(opponent)) => match (marks.get(&(opponent, player)), matrix.results.get(&(opponent, player))) {
+                (Some(mark), _) => mark,
+                (None, Some(result)) => theme.icon(result, matrix.format),
+                (None, None) => &theme.unplayable,
+            },
+            Some(None) => BYE_ICON,
+            None => &theme.unplayable,
+        };
+        row.push(' ');
+    }
+    row
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::matrix::tests::matrix;
+    use crate::results::MatchResult;
+
+    fn swiss_matrix(count: u64, rounds: u32) -> Matrix{
+        let mut matrix = matrix(count);
+        matrix.swiss = Some(SwissState::new(rounds));
+        matrix
+    }
+    fn play_round(matrix: &mut Matrix){
+        let swiss = matrix.swiss.as_ref().unwrap();
+        let pairings = pair_next_round(matrix, swiss).unwrap();
+        matrix.swiss.as_mut().unwrap().pairings.push(pairings.clone());
+        for (player, opponent) in pairings{
+            //Lower ids always win, so score groups separate quickly
+            if let Some(opponent) = opponent {
+                let result = if player.0 < opponent.0 {MatchResult::Score(2, 0)} else {MatchResult::Score(0, 2)};
+                matrix.set_result(player, opponent, result).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn default_rounds_find_a_single_winner() {
+        assert_eq!(SwissState::default_rounds(2), 1);
+        assert_eq!(SwissState::default_rounds(8), 3);
+        assert_eq!(SwissState::default_rounds(9), 4);
+        assert_eq!(SwissState::default_rounds(40), 6);
+    }
+
+    #[test]
+    fn odd_players_get_one_bye_each() {
+        let mut matrix = swiss_matrix(5, 5);
+        for _ in 0..5{
+            play_round(&mut matrix);
+            let swiss = matrix.swiss.as_ref().unwrap();
+            assert!(round_complete(&matrix, swiss));
+            assert_eq!(swiss.current_round().iter().filter(|(_, x)| x.is_none()).count(), 1);
+        }
+        let swiss = matrix.swiss.as_ref().unwrap();
+        assert!(swiss.is_finished());
+        assert!(matrix.users.iter().all(|x| swiss.byes(x.id) == 1));
+        assert!(pair_next_round(&matrix, swiss).is_err());
+    }
+
+    #[test]
+    fn rounds_avoid_rematches() {
+        let mut matrix = swiss_matrix(8, 3);
+        for _ in 0..3{
+            play_round(&mut matrix);
+        }
+        let swiss = matrix.swiss.as_ref().unwrap();
+        let mut seen = HashSet::new();
+        for (player, opponent) in swiss.pairings.iter().flatten(){
+            let opponent = opponent.unwrap();
+            assert!(seen.insert((player.0.min(opponent.0), player.0.max(opponent.0))), "rematch {} {}", player, opponent);
+        }
+        //Player 1 won every round so ends up the only one undefeated
+        assert_eq!(matrix.player_results(PlayerId(1)).filter(|(_, x)| x.is_win()).count(), 3);
+        assert_eq!(swiss.current_opponent(PlayerId(1)).map(|x| swiss.has_played(PlayerId(1), x)), Some(true));
+    }
+
+    #[test]
+    fn rows_show_a_square_per_round() {
+        let mut matrix = swiss_matrix(3, 2);
+        play_round(&mut matrix);
+        let swiss = matrix.swiss.as_ref().unwrap();
+        let rows: Vec<String> = matrix.users.iter().map(|x| render_row(&matrix, swiss, x.id, &Theme::default(), &Marks::new())).collect();
+        assert_eq!(rows.iter().filter(|x| x.contains(BYE_ICON)).count(), 1);
+        assert!(rows.iter().all(|x| x.split(' ').filter(|x| !x.is_empty()).count() == 1));
+        assert!(render_pairings(&matrix, swiss).starts_with("Round 1 of 2 pairings:"));
+    }
+}
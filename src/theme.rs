@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+
+use crate::grid::{PENDING_ICON, DISPUTE_ICON, BYE_ICON, MUTED_ICON};
+use crate::results::{MatchFormat, MatchResult, RUNGS};
+
+pub const PRESETS: [&str; 3] = ["moon", "circles", "hearts"];
+//Also used by the grid, so results can't look like them
+const RESERVED: [&str; 5] = [PENDING_ICON, DISPUTE_ICON, BYE_ICON, MUTED_ICON, ":asterisk:"];
+
+//The emoji a grid shows for each result
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Theme{
+    //Indexed by how many games the loser of the match won, so the clearest win comes first
+    pub wins: [String; RUNGS],
+    //Indexed the same way, so the heaviest loss comes first
+    pub losses: [String; RUNGS],
+    pub draw: String,
+    pub available: String,
+    pub unplayable: String,
+    //Shown on both sides of a forfeited match, the withdrawn player's row tells who won
+    #[serde(default = "default_forfeit")]
+    pub forfeit: String,
+}
+fn default_forfeit() -> String {
+    ":flag_white:".to_string()
+}
+impl Default for Theme{
+    fn default() -> Self {
+        Self::preset("moon").unwrap()
+    }
+}
+impl Theme{
+    pub fn preset(name: &str) -> Option<Self> {
+        let (wins, losses, draw) = match name{
+            "moon" => ([":full_moon:", ":waning_gibbous_moon:", ":last_quarter_moon:", ":waning_crescent_moon:"],
+                [":new_moon:", ":waxing_crescent_moon:", ":first_quarter_moon:", ":waxing_gibbous_moon:"], ":crescent_moon:"),
+            "circles" => ([":green_circle:", ":blue_circle:", ":purple_circle:", ":white_circle:"],
+                [":red_circle:", ":orange_circle:", ":yellow_circle:", ":brown_circle:"], ":radio_button:"),
+            "hearts" => ([":green_heart:", ":blue_heart:", ":purple_heart:", ":white_heart:"],
+                [":black_heart:", ":broken_heart:", ":orange_heart:", ":yellow_heart:"], ":heart_exclamation:"),
+            _ => return None,
+        };
+        Some(Theme{wins: wins.map(String::from), losses: losses.map(String::from), draw: draw.to_string(),
+            available: ":cloud:".to_string(), unplayable: ":black_small_square:".to_string(), forfeit: default_forfeit()})
+    }
+
+    //Reads a space separated list of exactly one icon per rung
+    pub fn parse_rungs(list: &str) -> Result<[String; RUNGS]> {
+        let icons: Vec<String> = list.split_whitespace().map(String::from).collect();
+        let count = icons.len();
+        icons.try_into().map_err(|_| anyhow!("expected {} icons separated by spaces but got {}", RUNGS, count))
+    }
+
+    //The preset's name or every icon, so a thread can record the theme it was posted in
+    pub fn to_text(&self) -> String {
+        match PRESETS.iter().find(|x| Self::preset(x).as_ref() == Some(self)) {
+            Some(name) => name.to_string(),
+            None => self.icons().collect::<Vec<_>>().join(" "),
+        }
+    }
+    pub fn from_text(text: &str) -> Result<Self> {
+        if let Some(theme) = Self::preset(text.trim()) {
+            return Ok(theme);
+        }
+        let icons: Vec<String> = text.split_whitespace().map(String::from).collect();
+        let [w0, w1, w2, w3, l0, l1, l2, l3, draw, available, unplayable, forfeit] = <[String; RUNGS*2+4]>::try_from(icons)
+            .map_err(|_| anyhow!("{} is not a theme", text))?;
+        let theme = Theme{wins: [w0, w1, w2, w3], losses: [l0, l1, l2, l3], draw, available, unplayable, forfeit};
+        theme.validate()?;
+        Ok(theme)
+    }
+
+    pub fn icons(&self) -> impl Iterator<Item = &str> {
+        self.wins.iter().chain(&self.losses).chain([&self.draw, &self.available, &self.unplayable, &self.forfeit]).map(|x| x.as_str())
+    }
+
+    //Checks every icon can be told apart from the others and from the rest of the grid when a thread is read back
+    pub fn validate(&self) -> Result<()> {
+        static RE_ICON: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(:[\w+-]+:|<a?:\w+:\d+>|[^\x00-\x7F]+)$").unwrap());
+        let mut seen = HashSet::new();
+        for icon in self.icons(){
+            if !RE_ICON.is_match(icon) {
+                return Err(anyhow!("{} is not an emoji, use a :shortcode:, a server emoji or a unicode emoji", icon));
+            }
+            if RESERVED.contains(&icon) || icon.starts_with(":regional_indicator_") || icon.starts_with(":number_") {
+                return Err(anyhow!("{} is already used by the grid", icon));
+            }
+            if !seen.insert(icon) {
+                return Err(anyhow!("{} is used for more than one result", icon));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn icon(&self, result: &MatchResult, format: MatchFormat) -> &str {
+        match result{
+            MatchResult::NotPlayed => &self.available,
+            MatchResult::Score(x, y) if x > y => &self.wins[format.rung(*y)],
+            MatchResult::Score(x, _) => &self.losses[format.rung(*x)],
+            MatchResult::Draw => &self.draw,
+            MatchResult::Unplayable => &self.unplayable,
+            MatchResult::Forfeit(_) => &self.forfeit,
+        }
+    }
+
+    //The result an icon stands for, forfeits are read as wins
+    pub fn result(&self, icon: &str, format: MatchFormat) -> Option<MatchResult> {
+        match icon{
+            _ if icon == self.available => Some(MatchResult::NotPlayed),
+            _ if icon == self.draw => Some(MatchResult::Draw),
+            _ if icon == self.unplayable => Some(MatchResult::Unplayable),
+            _ if icon == self.forfeit => Some(MatchResult::Forfeit(true)),
+            _ => {
+                if let Some(rung) = self.wins.iter().position(|x| x == icon){
+                    return Some(MatchResult::Score(format.first_to, format.loser_games(rung)));
+                }
+                let rung = self.losses.iter().position(|x| x == icon)?;
+                Some(MatchResult::Score(format.loser_games(rung), format.first_to))
+            },
+        }
+    }
+
+    pub fn legend(&self, format: MatchFormat) -> String {
+        let mut lines = vec![format!("{} match available", self.available)];
+        let mut icons: Vec<(&str, Vec<String>)> = Vec::new();
+        for score in format.scores(){
+            let icon = self.icon(&score, format);
+            match icons.last_mut(){
+                Some((last, scores)) if *last == icon => scores.push(score.to_str()),
+                _ => icons.push((icon, vec![score.to_str()])),
+            }
+        }
+        for (icon, scores) in icons{
+            let won = if MatchResult::get(&scores[0]).is_some_and(|x| x.is_win()) {"won"} else {"lost"};
+            lines.push(format!("{} match {} {}", icon, won, scores.join(" or ")));
+        }
+        if format.draws {
+            lines.push(format!("{} match drawn", self.draw));
+        }
+        lines.push(format!("{} cannot play yourself", self.unplayable));
+        lines.push(format!("{} match forfeited by a withdrawn player", self.forfeit));
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests{
+    use super::*;
+
+    fn formats() -> impl Iterator<Item = MatchFormat> {
+        (1..=MatchFormat::MAX_FIRST_TO).flat_map(|first_to| [false, true].map(|draws| MatchFormat{first_to, draws}))
+    }
+    pub fn custom() -> Theme {
+        Theme{wins: Theme::parse_rungs("<:w0:10> <:w1:11> <a:w2:12> 🟩").unwrap(), losses: Theme::parse_rungs(":skull: <:l1:21> <:l2:22> 🟥").unwrap(),
+            draw: ":handshake:".to_string(), available: "⬜".to_string(), unplayable: ":x:".to_string(), forfeit: "🏳️".to_string()}
+    }
+
+    #[test]
+    fn presets_and_custom_themes_are_valid() {
+        for name in PRESETS{
+            Theme::preset(name).unwrap().validate().unwrap();
+        }
+        custom().validate().unwrap();
+        assert!(Theme::preset("stars").is_none());
+    }
+
+    #[test]
+    fn themes_read_back_from_their_text() {
+        assert_eq!(Theme::preset("hearts").unwrap().to_text(), "hearts");
+        for theme in PRESETS.iter().map(|x| Theme::preset(x).unwrap()).chain([custom()]){
+            assert_eq!(Theme::from_text(&theme.to_text()).unwrap(), theme);
+        }
+        assert!(Theme::from_text("stars").is_err());
+    }
+
+    #[test]
+    fn ambiguous_icons_are_rejected() {
+        let mut theme = custom();
+        theme.draw = theme.wins[0].clone();
+        assert!(theme.validate().is_err());
+        theme.draw = PENDING_ICON.to_string();
+        assert!(theme.validate().is_err());
+        theme.draw = ":regional_indicator_a:".to_string();
+        assert!(theme.validate().is_err());
+        theme.draw = "draw".to_string();
+        assert!(theme.validate().is_err());
+        assert!(Theme::parse_rungs(":a: :b: :c:").is_err());
+    }
+
+    #[test]
+    fn icons_read_back_as_a_score_with_the_same_icon() {
+        for theme in PRESETS.iter().map(|x| Theme::preset(x).unwrap()).chain([custom()]){
+            for format in formats(){
+                for score in format.scores().into_iter().chain([MatchResult::NotPlayed, MatchResult::Draw, MatchResult::Unplayable, MatchResult::Forfeit(true)]){
+                    let icon = theme.icon(&score, format);
+                    let parsed = theme.result(icon, format).unwrap();
+                    assert_eq!(theme.icon(&parsed, format), icon);
+                    assert_eq!(parsed, score);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn legend_mentions_every_score() {
+        for format in formats(){
+            let legend = custom().legend(format);
+            for score in format.scores(){
+                assert!(legend.contains(&score.to_str()), "{} missing from {}", score.to_str(), legend);
+            }
+            assert_eq!(legend.contains("match drawn"), format.draws);
+            assert!(legend.starts_with("⬜ match available"));
+            assert!(legend.ends_with("🏳️ match forfeited by a withdrawn player"));
+        }
+    }
+}